@@ -1,15 +1,210 @@
-use color_eyre::eyre::Error;
-
-fn main() -> Result<(), Error> {
-    // todo: accept these as arguments from command line and/or stdin
-    let mod_name = "node_runtime";
-    let path = {
-        let root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".into());
-        let root_path = std::path::Path::new(&root);
-        root_path.join("../core/node-runtime.scale")
+use color_eyre::eyre::{self, WrapErr};
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use structopt::{clap::AppSettings, StructOpt};
+
+mod rpc;
+
+/// Generate a Rust module with all the types for a substrate runtime from its metadata.
+#[derive(Debug, StructOpt)]
+#[structopt(name = "chameleon", setting = AppSettings::SubcommandsNegateReqs)]
+struct Opts {
+    #[structopt(subcommand)]
+    command: Option<Command>,
+    /// Path to the runtime metadata file, or `-` to read it from stdin. The metadata can be SCALE
+    /// encoded, `0x` prefixed hex, or a JSON-RPC `state_getMetadata` response.
+    #[structopt(
+        long,
+        short,
+        parse(from_os_str),
+        required_unless = "url",
+        conflicts_with = "url"
+    )]
+    metadata: Option<PathBuf>,
+    /// Fetch the metadata from the node at the given `ws://`, `http://` or `https://` url.
+    #[structopt(long)]
+    url: Option<String>,
+    /// The hash of the block to fetch the metadata at, defaults to the best block.
+    #[structopt(long, requires = "url")]
+    at: Option<String>,
+    /// The timeout in seconds for fetching the metadata from a node.
+    #[structopt(long, default_value = "30")]
+    timeout: u64,
+    /// The name of the generated runtime module.
+    #[structopt(long, default_value = "node_runtime")]
+    module_name: String,
+    /// The file to write the generated code to, defaults to stdout.
+    #[structopt(long, short, parse(from_os_str))]
+    output: Option<PathBuf>,
+    /// Use `primitive_types::U256` for `U256` primitives, instead of generating a self-contained type.
+    #[structopt(long)]
+    primitive_types: bool,
+    /// Additional derive for all generated types, e.g. `Clone`. Can be repeated.
+    #[structopt(long = "derive", number_of_values = 1, parse(try_from_str = syn::parse_str))]
+    derives: Vec<syn::Path>,
+    /// Additional derive for a single generated type, as `<TYPE_PATH>=<DERIVE>`, e.g.
+    /// `sp_core::crypto::AccountId32=Hash`. Can be repeated.
+    #[structopt(
+        long = "derive-for-type",
+        number_of_values = 1,
+        parse(try_from_str = parse_type_and_path)
+    )]
+    type_derives: Vec<(String, syn::Path)>,
+    /// Use an existing Rust type instead of generating a type, as `<TYPE_PATH>=<RUST_PATH>`, e.g.
+    /// `sp_core::crypto::AccountId32=::sp_core::crypto::AccountId32`. Can be repeated.
+    #[structopt(
+        long = "substitute-type",
+        number_of_values = 1,
+        parse(try_from_str = parse_type_and_path)
+    )]
+    type_substitutes: Vec<(String, syn::Path)>,
+    /// The type of the additional signed data of a signed extension unknown to the generator, as
+    /// `<IDENTIFIER>=<RUST_TYPE>`, e.g. `CheckFoo=u32`. Can be repeated.
+    #[structopt(
+        long = "additional-signed",
+        number_of_values = 1,
+        parse(try_from_str = parse_additional_signed)
+    )]
+    additional_signed_types: Vec<(String, syn::Type)>,
+    /// Path to a file mapping the type names of legacy (V12) metadata onto Rust types, with a
+    /// `<TYPE_NAME> = <RUST_TYPE>` mapping on each line, e.g. `T::Balance = u128`.
+    #[structopt(long, parse(from_os_str))]
+    legacy_types: Option<PathBuf>,
+    /// Embed the structural hashes of the pallets and their items, with a
+    /// `check_compatibility` function to check the metadata of a node against them at runtime.
+    #[structopt(long)]
+    compatibility_check: bool,
+    /// Generate `EventRecords::decode_tolerant`, which decodes the events of a block with the
+    /// metadata of a node, keeping the bytes of events unknown to the generated code.
+    #[structopt(long)]
+    tolerant_events: bool,
+    /// Path to a file declaring runtime API methods to generate `state_call` functions for, with
+    /// a `<Api>_<method>(<arg>: <type id>, ..) -> <type id>` declaration on each line, e.g.
+    /// `AccountNonceApi_account_nonce(account: 0) -> 4`.
+    #[structopt(long, parse(from_os_str))]
+    runtime_apis: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Compare the metadata of two runtime versions, and print the added, removed and renamed
+    /// pallets, calls, events and storage entries, and the changes to their types.
+    Diff {
+        /// Path to the metadata of the old runtime, or `-` to read it from stdin.
+        #[structopt(parse(from_os_str))]
+        old: PathBuf,
+        /// Path to the metadata of the new runtime, or `-` to read it from stdin.
+        #[structopt(parse(from_os_str))]
+        new: PathBuf,
+        /// Print the changes as JSON.
+        #[structopt(long)]
+        json: bool,
+    },
+}
+
+fn main() -> color_eyre::Result<()> {
+    color_eyre::install()?;
+    let opts = Opts::from_args();
+
+    if let Some(Command::Diff { old, new, json }) = opts.command {
+        return diff(&old, &new, json);
+    }
+
+    let bytes = match (opts.metadata, opts.url) {
+        (Some(path), _) => read_metadata(&path)?,
+        (None, Some(url)) => {
+            let timeout = Duration::from_secs(opts.timeout);
+            rpc::fetch_metadata(&url, opts.at.as_deref(), timeout)?
+        }
+        (None, None) => unreachable!("either --metadata or --url is required"),
+    };
+    let mut config = core::TypeGeneratorConfig {
+        primitive_types: opts.primitive_types,
+        compatibility_check: opts.compatibility_check,
+        tolerant_events: opts.tolerant_events,
+        ..Default::default()
     };
+    for derive in opts.derives {
+        config = config.derive(derive);
+    }
+    for (type_path, derive) in opts.type_derives {
+        config = config.derive_for_type(type_path, derive);
+    }
+    for (type_path, substitute) in opts.type_substitutes {
+        config = config.substitute_type(type_path, substitute);
+    }
+    for (identifier, ty) in opts.additional_signed_types {
+        config = config.additional_signed_type(identifier, ty);
+    }
+    if let Some(path) = opts.legacy_types {
+        let source = fs::read_to_string(&path)
+            .wrap_err_with(|| format!("Failed to read legacy types file {}", path.display()))?;
+        config.legacy_types = core::LegacyTypeRegistry::parse(&source)
+            .wrap_err_with(|| format!("Invalid legacy types file {}", path.display()))?;
+    }
+    if let Some(path) = opts.runtime_apis {
+        let source = fs::read_to_string(&path)
+            .wrap_err_with(|| format!("Failed to read runtime APIs file {}", path.display()))?;
+        config.runtime_apis = core::RuntimeApis::parse(&source)
+            .wrap_err_with(|| format!("Invalid runtime APIs file {}", path.display()))?;
+    }
+    let code = core::generate_runtime_types_from_bytes(&opts.module_name, &bytes, config)
+        .wrap_err("Failed to generate runtime types")?;
+    let source = core::format_source(code).wrap_err("Failed to format generated code")?;
 
-    let code = core::generate_runtime_types(mod_name, path)?;
-    println!("{}", code.to_string());
+    match opts.output {
+        Some(path) => fs::write(&path, source)
+            .wrap_err_with(|| format!("Failed to write output file {}", path.display()))?,
+        None => print!("{}", source),
+    }
     Ok(())
 }
+
+fn diff(old: &Path, new: &Path, json: bool) -> eyre::Result<()> {
+    if old.as_os_str() == "-" && new.as_os_str() == "-" {
+        eyre::bail!("Only one of the old and new metadata can be read from stdin");
+    }
+    let diff = core::diff::diff_metadata(&read_metadata(old)?, &read_metadata(new)?)
+        .wrap_err("Failed to compare the metadata")?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+    } else if diff.is_empty() {
+        println!("No changes");
+    } else {
+        print!("{}", diff);
+    }
+    Ok(())
+}
+
+fn read_metadata(path: &Path) -> eyre::Result<Vec<u8>> {
+    if path.as_os_str() == "-" {
+        let mut bytes = Vec::new();
+        io::stdin()
+            .read_to_end(&mut bytes)
+            .wrap_err("Failed to read metadata from stdin")?;
+        Ok(bytes)
+    } else {
+        fs::read(path).wrap_err_with(|| format!("Failed to read metadata file {}", path.display()))
+    }
+}
+
+/// Parse a `<TYPE_PATH>=<RUST_PATH>` pair of a metadata type path and a Rust path.
+fn parse_type_and_path(s: &str) -> eyre::Result<(String, syn::Path)> {
+    let (type_path, path) = s
+        .split_once('=')
+        .ok_or_else(|| eyre::eyre!("Expected `<TYPE_PATH>=<RUST_PATH>`, got `{}`", s))?;
+    let path = syn::parse_str(path).wrap_err_with(|| format!("Invalid Rust path `{}`", path))?;
+    Ok((type_path.trim().to_string(), path))
+}
+
+fn parse_additional_signed(s: &str) -> eyre::Result<(String, syn::Type)> {
+    let (identifier, ty) = s
+        .split_once('=')
+        .ok_or_else(|| eyre::eyre!("Expected `<IDENTIFIER>=<RUST_TYPE>`, got `{}`", s))?;
+    let ty = syn::parse_str(ty).wrap_err_with(|| format!("Invalid Rust type `{}`", ty))?;
+    Ok((identifier.trim().to_string(), ty))
+}
@@ -0,0 +1,176 @@
+//! Fetching the runtime metadata from a node over JSON-RPC.
+
+use color_eyre::eyre::{self, WrapErr};
+use std::{
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+use tungstenite::Message;
+
+/// Request the metadata from the node at the given `ws://`, `http://` or `https://` url, optionally
+/// at the given block hash.
+///
+/// Returns the body of the `state_getMetadata` JSON-RPC response, which contains the hex encoded
+/// metadata.
+pub fn fetch_metadata(url: &str, at: Option<&str>, timeout: Duration) -> eyre::Result<Vec<u8>> {
+    let params = at.map_or_else(Vec::new, |hash| vec![hash]);
+    let request = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "state_getMetadata",
+        "params": params,
+    })
+    .to_string();
+
+    if url.starts_with("ws://") {
+        fetch_ws(url, &request, timeout)
+    } else if url.starts_with("http://") || url.starts_with("https://") {
+        fetch_http(url, &request, timeout)
+    } else {
+        eyre::bail!(
+            "Unsupported url `{}`, expected a `ws://`, `http://` or `https://` url",
+            url
+        )
+    }
+}
+
+fn fetch_http(url: &str, request: &str, timeout: Duration) -> eyre::Result<Vec<u8>> {
+    let agent = ureq::AgentBuilder::new().timeout(timeout).build();
+    let response = agent
+        .post(url)
+        .set("Content-Type", "application/json")
+        .send_string(request)
+        .wrap_err_with(|| format!("Failed to request metadata from {}", url))?;
+    let body = response
+        .into_string()
+        .wrap_err_with(|| format!("Failed to read the response from {}", url))?;
+    Ok(body.into_bytes())
+}
+
+fn fetch_ws(url: &str, request: &str, timeout: Duration) -> eyre::Result<Vec<u8>> {
+    let uri: tungstenite::http::Uri = url
+        .parse()
+        .wrap_err_with(|| format!("Invalid url {}", url))?;
+    let host = uri
+        .host()
+        .ok_or_else(|| eyre::eyre!("Missing host in url {}", url))?;
+    let port = uri.port_u16().unwrap_or(80);
+    let addr = (host, port)
+        .to_socket_addrs()
+        .wrap_err_with(|| format!("Failed to resolve {}", url))?
+        .next()
+        .ok_or_else(|| eyre::eyre!("No address found for {}", url))?;
+
+    let stream = TcpStream::connect_timeout(&addr, timeout)
+        .wrap_err_with(|| format!("Failed to connect to {}", url))?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let (mut socket, _) = tungstenite::client(url, stream)
+        .map_err(|err| eyre::eyre!("WebSocket handshake with {} failed: {}", url, err))?;
+
+    socket
+        .send(Message::Text(request.to_string()))
+        .wrap_err_with(|| format!("Failed to request metadata from {}", url))?;
+    loop {
+        let message = socket
+            .read()
+            .wrap_err_with(|| format!("Failed to read the response from {}", url))?;
+        match message {
+            Message::Text(text) => {
+                // the response has been received, so failing to close the connection doesn't matter
+                let _ = socket.close(None);
+                return Ok(text.into_bytes());
+            }
+            Message::Binary(bytes) => {
+                let _ = socket.close(None);
+                return Ok(bytes);
+            }
+            Message::Close(_) => eyre::bail!("{} closed the connection before responding", url),
+            _ => continue,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, net::TcpListener, path, thread};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// A `state_getMetadata` response with the metadata of the node runtime used by the core tests.
+    fn metadata_response() -> String {
+        let root = env::var("CARGO_MANIFEST_DIR").unwrap_or(".".into());
+        let path = path::Path::new(&root).join("../core/node-runtime.scale");
+        let metadata = fs::read(path).unwrap();
+        format!(
+            r#"{{"jsonrpc":"2.0","result":"0x{}","id":1}}"#,
+            hex::encode(metadata)
+        )
+    }
+
+    #[test]
+    fn fetch_metadata_over_http() {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr());
+        let response = metadata_response();
+        let served = response.clone();
+        let handle = thread::spawn(move || {
+            let mut request = server.recv().unwrap();
+            let mut body = String::new();
+            request.as_reader().read_to_string(&mut body).unwrap();
+            request
+                .respond(tiny_http::Response::from_string(served))
+                .unwrap();
+            body
+        });
+
+        let body = fetch_metadata(&url, Some("0x1234"), TIMEOUT).unwrap();
+        let request: serde_json::Value = serde_json::from_str(&handle.join().unwrap()).unwrap();
+
+        assert_eq!(body, response.into_bytes());
+        assert_eq!(request["method"], "state_getMetadata");
+        assert_eq!(request["params"], serde_json::json!(["0x1234"]));
+    }
+
+    #[test]
+    fn fetch_metadata_over_ws() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let response = metadata_response();
+        let served = response.clone();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            let request = socket.read().unwrap().into_text().unwrap();
+            socket.send(Message::Text(served)).unwrap();
+            request
+        });
+
+        let body = fetch_metadata(&url, None, TIMEOUT).unwrap();
+        let request: serde_json::Value = serde_json::from_str(&handle.join().unwrap()).unwrap();
+
+        assert_eq!(body, response.into_bytes());
+        assert_eq!(request["method"], "state_getMetadata");
+        assert_eq!(request["params"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn unresponsive_node_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        // accept the connection but never respond
+        let _handle = thread::spawn(move || listener.accept().map(|(stream, _)| stream));
+
+        let result = fetch_metadata(&url, None, Duration::from_millis(100));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn unsupported_url_is_an_error() {
+        let result = fetch_metadata("ftp://127.0.0.1", None, TIMEOUT);
+
+        assert!(result.unwrap_err().to_string().contains("Unsupported url"));
+    }
+}
@@ -0,0 +1,639 @@
+//! Structural hashes of the pallets and their items in the runtime metadata, to check
+//! whether the metadata of a live node is still compatible with the generated code.
+//!
+//! The hashes are derived from the shape of the types in the type registry: the names and types
+//! of fields, the names and indices of variants, primitives and array lengths. Type ids, paths and
+//! docs are ignored, so the hashes are stable between runtime versions which don't change the
+//! encoding of an item.
+
+use crate::{
+    generate_storage::storage_entry_types,
+    generate_types::{call_index, variant_index},
+    Error, Result,
+};
+use frame_metadata::v13::{
+    FunctionMetadata, PalletMetadata, RuntimeMetadataV13, StorageEntryMetadata,
+    StorageEntryModifier, StorageHasher,
+};
+use scale::Encode as _;
+use scale_info::{form::PortableForm, prelude::num::NonZeroU32, PortableRegistry, TypeDef};
+use std::fmt;
+
+/// A structural hash, the `twox_128` hash of the shape of an item.
+pub type Hash = u128;
+
+/// The hashes of a pallet and of its calls, events, errors and storage entries, as embedded in
+/// the generated code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PalletHashes {
+    pub name: &'static str,
+    /// The index of the pallet, which prefixes its calls and events.
+    pub index: u8,
+    /// The hash of the whole pallet, including its index, constants and all of its items.
+    pub hash: Hash,
+    pub calls: &'static [(&'static str, Hash)],
+    pub events: &'static [(&'static str, Hash)],
+    pub errors: &'static [(&'static str, Hash)],
+    pub storage: &'static [(&'static str, Hash)],
+}
+
+/// Check whether the pallets, calls, events, errors and storage entries of the metadata have the
+/// same hashes as the expected ones, e.g. those embedded in the generated code.
+///
+/// The metadata can be in any of the formats accepted by
+/// [`generate_runtime_types_from_bytes`](crate::generate_runtime_types_from_bytes). Pallets and
+/// items of the metadata which are not expected are ignored.
+pub fn check_compatibility(
+    metadata: &[u8],
+    expected: &[PalletHashes],
+) -> std::result::Result<(), Incompatibilities> {
+    changed_pallets(metadata, expected).map(|_| ())
+}
+
+/// Check the compatibility of the metadata like [`check_compatibility`], and return the names of
+/// the expected pallets whose hash changed while their index and expected items are unchanged,
+/// e.g. because of a new event or a changed constant.
+pub fn changed_pallets(
+    metadata: &[u8],
+    expected: &[PalletHashes],
+) -> std::result::Result<Vec<String>, Incompatibilities> {
+    let metadata =
+        crate::decode_metadata_v13(metadata).map_err(Incompatibilities::InvalidMetadata)?;
+    let pallets = metadata_hashes(&metadata).map_err(Incompatibilities::InvalidMetadata)?;
+
+    let mut changed_pallets = Vec::new();
+    let mut incompatibilities = Vec::new();
+    for expected in expected {
+        let pallet = match pallets.iter().find(|p| p.name == expected.name) {
+            Some(pallet) if pallet.hash == expected.hash => continue,
+            Some(pallet) => pallet,
+            None => {
+                incompatibilities.push(Incompatibility::MissingPallet {
+                    pallet: expected.name.to_string(),
+                });
+                continue;
+            }
+        };
+        let count = incompatibilities.len();
+        if pallet.index != expected.index {
+            incompatibilities.push(Incompatibility::PalletIndexChanged {
+                pallet: pallet.name.clone(),
+            });
+        }
+
+        type Constructor = fn(String, String) -> Incompatibility;
+        let mut compare = |live: &[(String, Hash)],
+                           expected: &[(&str, Hash)],
+                           missing: Constructor,
+                           changed: Constructor| {
+            for (name, hash) in expected {
+                match live.iter().find(|(live, _)| live == name) {
+                    Some((_, live)) if live == hash => (),
+                    Some(_) => {
+                        incompatibilities.push(changed(pallet.name.clone(), name.to_string()))
+                    }
+                    None => incompatibilities.push(missing(pallet.name.clone(), name.to_string())),
+                }
+            }
+        };
+        compare(
+            &pallet.calls,
+            expected.calls,
+            |pallet, call| Incompatibility::MissingCall { pallet, call },
+            |pallet, call| Incompatibility::CallChanged { pallet, call },
+        );
+        compare(
+            &pallet.events,
+            expected.events,
+            |pallet, event| Incompatibility::MissingEvent { pallet, event },
+            |pallet, event| Incompatibility::EventChanged { pallet, event },
+        );
+        compare(
+            &pallet.errors,
+            expected.errors,
+            |pallet, error| Incompatibility::MissingError { pallet, error },
+            |pallet, error| Incompatibility::ErrorChanged { pallet, error },
+        );
+        compare(
+            &pallet.storage,
+            expected.storage,
+            |pallet, storage| Incompatibility::MissingStorage { pallet, storage },
+            |pallet, storage| Incompatibility::StorageChanged { pallet, storage },
+        );
+        if incompatibilities.len() == count {
+            changed_pallets.push(pallet.name.clone());
+        }
+    }
+
+    if incompatibilities.is_empty() {
+        Ok(changed_pallets)
+    } else {
+        Err(Incompatibilities::Changed(incompatibilities))
+    }
+}
+
+/// The reasons why the metadata of a node is not compatible with the generated code.
+#[derive(thiserror::Error, Debug)]
+pub enum Incompatibilities {
+    #[error("Invalid runtime metadata: {0}")]
+    InvalidMetadata(#[source] Error),
+    #[error("Incompatible runtime metadata: {}", join(.0))]
+    Changed(Vec<Incompatibility>),
+}
+
+fn join(incompatibilities: &[Incompatibility]) -> String {
+    incompatibilities
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A pallet or an item of a pallet which is missing or changed in the metadata of a node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Incompatibility {
+    MissingPallet { pallet: String },
+    PalletIndexChanged { pallet: String },
+    MissingCall { pallet: String, call: String },
+    CallChanged { pallet: String, call: String },
+    MissingEvent { pallet: String, event: String },
+    EventChanged { pallet: String, event: String },
+    MissingError { pallet: String, error: String },
+    ErrorChanged { pallet: String, error: String },
+    MissingStorage { pallet: String, storage: String },
+    StorageChanged { pallet: String, storage: String },
+}
+
+impl fmt::Display for Incompatibility {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingPallet { pallet } => write!(f, "pallet {} is missing", pallet),
+            Self::PalletIndexChanged { pallet } => {
+                write!(f, "the index of pallet {} changed", pallet)
+            }
+            Self::MissingCall { pallet, call } => {
+                write!(f, "call {}::{} is missing", pallet, call)
+            }
+            Self::CallChanged { pallet, call } => write!(f, "call {}::{} changed", pallet, call),
+            Self::MissingEvent { pallet, event } => {
+                write!(f, "event {}::{} is missing", pallet, event)
+            }
+            Self::EventChanged { pallet, event } => {
+                write!(f, "event {}::{} changed", pallet, event)
+            }
+            Self::MissingError { pallet, error } => {
+                write!(f, "error {}::{} is missing", pallet, error)
+            }
+            Self::ErrorChanged { pallet, error } => {
+                write!(f, "error {}::{} changed", pallet, error)
+            }
+            Self::MissingStorage { pallet, storage } => {
+                write!(f, "storage {}::{} is missing", pallet, storage)
+            }
+            Self::StorageChanged { pallet, storage } => {
+                write!(f, "storage {}::{} changed", pallet, storage)
+            }
+        }
+    }
+}
+
+/// The hashes of a pallet and of its calls, events, errors and storage entries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedPalletHashes {
+    pub name: String,
+    pub index: u8,
+    pub hash: Hash,
+    pub calls: Vec<(String, Hash)>,
+    pub events: Vec<(String, Hash)>,
+    pub errors: Vec<(String, Hash)>,
+    pub storage: Vec<(String, Hash)>,
+}
+
+/// Compute the hashes of all pallets of the metadata.
+pub fn metadata_hashes(metadata: &RuntimeMetadataV13) -> Result<Vec<OwnedPalletHashes>> {
+    let mut hasher = TypeHasher {
+        types: &metadata.types,
+        stack: Vec::new(),
+    };
+    metadata
+        .pallets
+        .iter()
+        .map(|pallet| hasher.hash_pallet(pallet))
+        .collect()
+}
+
+/// Hashes the shape of types, as the hash of a tag for the kind of type followed by its details
+/// and the hashes of the types it contains.
+struct TypeHasher<'a> {
+    types: &'a PortableRegistry,
+    /// The types currently being hashed, to hash references back to a recursive type by their
+    /// position in the stack rather than following them.
+    stack: Vec<NonZeroU32>,
+}
+
+impl<'a> TypeHasher<'a> {
+    fn hash_pallet(&mut self, pallet: &PalletMetadata<PortableForm>) -> Result<OwnedPalletHashes> {
+        let calls = pallet
+            .calls
+            .iter()
+            .flat_map(|calls| calls.calls.iter().map(move |call| (calls, call)))
+            .map(|(calls, call)| {
+                let index = call_index(self.types, calls, &call.name)?;
+                Ok((call.name.clone(), self.hash_call(index, call)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let storage = pallet
+            .storage
+            .iter()
+            .flat_map(|storage| storage.entries.iter())
+            .map(|entry| Ok((entry.name.clone(), self.hash_storage_entry(entry)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let events = self.hash_variants(pallet.event.as_ref().map(|event| event.ty.id()))?;
+        let errors = self.hash_variants(pallet.error.as_ref().map(|error| error.ty.id()))?;
+
+        let mut bytes = Vec::new();
+        pallet.name.encode_to(&mut bytes);
+        pallet.index.encode_to(&mut bytes);
+        for (_, hash) in calls.iter().chain(&events).chain(&errors).chain(&storage) {
+            bytes.extend_from_slice(&hash.to_le_bytes());
+        }
+        let event = pallet.event.as_ref().map(|event| event.ty.id());
+        self.hash_optional_type(&mut bytes, event)?;
+        for constant in &pallet.constants {
+            constant.name.encode_to(&mut bytes);
+            self.hash_type_into(&mut bytes, constant.ty.id())?;
+        }
+        let error = pallet.error.as_ref().map(|error| error.ty.id());
+        self.hash_optional_type(&mut bytes, error)?;
+
+        Ok(OwnedPalletHashes {
+            name: pallet.name.clone(),
+            index: pallet.index,
+            hash: hash(&bytes),
+            calls,
+            events,
+            errors,
+            storage,
+        })
+    }
+
+    /// Hash each variant of the event or error type of a pallet, with its index and fields. The
+    /// `__Ignore` marker variant of V13 metadata is skipped, as it isn't counted by the runtime.
+    fn hash_variants(&mut self, id: Option<NonZeroU32>) -> Result<Vec<(String, Hash)>> {
+        let id = match id {
+            Some(id) => id,
+            None => return Ok(Vec::new()),
+        };
+        let types = self.types;
+        let ty = types
+            .resolve(id)
+            .ok_or(Error::TypeNotFound { id: id.get() })?;
+        let variants = match ty.type_def() {
+            TypeDef::Variant(variant) => variant.variants(),
+            _ => return Err(Error::ExpectedVariantType { id: id.get() }),
+        };
+
+        self.stack.push(id);
+        let hashes = variants
+            .iter()
+            .filter(|v| v.name() != "__Ignore")
+            .enumerate()
+            .map(|(position, v)| {
+                let mut bytes = Vec::new();
+                v.name().encode_to(&mut bytes);
+                variant_index(position, v)?.encode_to(&mut bytes);
+                self.hash_fields(&mut bytes, v.fields())?;
+                Ok((v.name().clone(), hash(&bytes)))
+            })
+            .collect::<Result<Vec<_>>>();
+        self.stack.pop();
+        hashes
+    }
+
+    fn hash_call(&mut self, index: u8, call: &FunctionMetadata<PortableForm>) -> Result<Hash> {
+        let mut bytes = Vec::new();
+        call.name.encode_to(&mut bytes);
+        index.encode_to(&mut bytes);
+        for arg in &call.arguments {
+            arg.name.encode_to(&mut bytes);
+            self.hash_type_into(&mut bytes, arg.ty.id())?;
+        }
+        Ok(hash(&bytes))
+    }
+
+    fn hash_storage_entry(&mut self, entry: &StorageEntryMetadata<PortableForm>) -> Result<Hash> {
+        let (keys, hashers, value) = storage_entry_types(self.types, entry);
+        let mut bytes = Vec::new();
+        entry.name.encode_to(&mut bytes);
+        bytes.push(match entry.modifier {
+            StorageEntryModifier::Optional => 0,
+            StorageEntryModifier::Default => 1,
+        });
+        for hasher in hashers {
+            bytes.push(hasher_index(hasher));
+        }
+        for key in keys {
+            self.hash_type_into(&mut bytes, key)?;
+        }
+        self.hash_type_into(&mut bytes, value)?;
+        Ok(hash(&bytes))
+    }
+
+    fn hash_optional_type(&mut self, bytes: &mut Vec<u8>, id: Option<NonZeroU32>) -> Result<()> {
+        match id {
+            Some(id) => {
+                bytes.push(1);
+                self.hash_type_into(bytes, id)?;
+            }
+            None => bytes.push(0),
+        }
+        Ok(())
+    }
+
+    /// Append the hash of the type to the bytes to be hashed.
+    fn hash_type_into(&mut self, bytes: &mut Vec<u8>, id: NonZeroU32) -> Result<()> {
+        let hash = self.hash_type(id)?;
+        bytes.extend_from_slice(&hash.to_le_bytes());
+        Ok(())
+    }
+
+    fn hash_type(&mut self, id: NonZeroU32) -> Result<Hash> {
+        if let Some(depth) = self.stack.iter().position(|ty| *ty == id) {
+            return Ok(hash(&[&b"R"[..], &(depth as u32).to_le_bytes()].concat()));
+        }
+        let ty = self
+            .types
+            .resolve(id)
+            .ok_or(Error::TypeNotFound { id: id.get() })?;
+
+        self.stack.push(id);
+        let mut bytes = Vec::new();
+        match ty.type_def() {
+            TypeDef::Composite(composite) => {
+                bytes.push(b'C');
+                self.hash_fields(&mut bytes, composite.fields())?;
+            }
+            TypeDef::Variant(variant) => {
+                bytes.push(b'V');
+                for (position, v) in variant.variants().iter().enumerate() {
+                    v.name().encode_to(&mut bytes);
+                    variant_index(position, v)?.encode_to(&mut bytes);
+                    self.hash_fields(&mut bytes, v.fields())?;
+                }
+            }
+            TypeDef::Sequence(sequence) => {
+                bytes.push(b'S');
+                self.hash_type_into(&mut bytes, sequence.type_param().id())?;
+            }
+            TypeDef::Array(array) => {
+                bytes.push(b'A');
+                array.len().encode_to(&mut bytes);
+                self.hash_type_into(&mut bytes, array.type_param().id())?;
+            }
+            TypeDef::Tuple(tuple) => {
+                bytes.push(b'T');
+                for field in tuple.fields() {
+                    self.hash_type_into(&mut bytes, field.id())?;
+                }
+            }
+            TypeDef::Primitive(primitive) => {
+                bytes.push(b'P');
+                format!("{:?}", primitive).encode_to(&mut bytes);
+            }
+            TypeDef::Compact(compact) => {
+                bytes.push(b'c');
+                self.hash_type_into(&mut bytes, compact.type_param().id())?;
+            }
+            TypeDef::Phantom(_) => bytes.push(b'p'),
+        }
+        self.stack.pop();
+        Ok(hash(&bytes))
+    }
+
+    fn hash_fields(
+        &mut self,
+        bytes: &mut Vec<u8>,
+        fields: &[scale_info::Field<PortableForm>],
+    ) -> Result<()> {
+        scale::Compact(fields.len() as u32).encode_to(bytes);
+        for field in fields {
+            field.name().encode_to(bytes);
+            self.hash_type_into(bytes, field.ty().id())?;
+        }
+        Ok(())
+    }
+}
+
+fn hash(bytes: &[u8]) -> Hash {
+    u128::from_le_bytes(crate::generate_storage::twox_128(bytes))
+}
+
+fn hasher_index(hasher: &StorageHasher) -> u8 {
+    match hasher {
+        StorageHasher::Blake2_128 => 0,
+        StorageHasher::Blake2_256 => 1,
+        StorageHasher::Blake2_128Concat => 2,
+        StorageHasher::Twox128 => 3,
+        StorageHasher::Twox256 => 4,
+        StorageHasher::Twox64Concat => 5,
+        StorageHasher::Identity => 6,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::read_metadata;
+
+    /// The hashes of the metadata, as they would be embedded in the generated code.
+    fn expected_hashes(metadata: &[u8]) -> Vec<PalletHashes> {
+        fn leak(items: Vec<(String, Hash)>) -> &'static [(&'static str, Hash)] {
+            let items = items
+                .into_iter()
+                .map(|(name, hash)| (&*Box::leak(name.into_boxed_str()), hash))
+                .collect::<Vec<_>>();
+            Box::leak(items.into_boxed_slice())
+        }
+
+        let metadata = crate::decode_metadata_v13(metadata).unwrap();
+        metadata_hashes(&metadata)
+            .unwrap()
+            .into_iter()
+            .map(|pallet| PalletHashes {
+                name: Box::leak(pallet.name.into_boxed_str()),
+                index: pallet.index,
+                hash: pallet.hash,
+                calls: leak(pallet.calls),
+                events: leak(pallet.events),
+                errors: leak(pallet.errors),
+                storage: leak(pallet.storage),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_metadata_is_compatible() {
+        for file in &["node-runtime.scale", "test-runtime-v14-indices.scale"] {
+            let metadata = read_metadata(file);
+
+            let result = changed_pallets(&metadata, &expected_hashes(&metadata));
+
+            assert_eq!(result.unwrap(), Vec::<String>::new());
+        }
+    }
+
+    #[test]
+    fn hashes_are_independent_of_type_ids() {
+        // the upgraded runtime registers its types in a different order, so the type of the
+        // argument of the unchanged `remark` call has a different id
+        let old = crate::decode_metadata_v13(&read_metadata("test-runtime-v14.scale")).unwrap();
+        let new =
+            crate::decode_metadata_v13(&read_metadata("test-runtime-v14-upgrade.scale")).unwrap();
+        let old_call = &old.pallets[0].calls.as_ref().unwrap().calls[0];
+        let new_call = &new.pallets[0].calls.as_ref().unwrap().calls[0];
+        assert_eq!(new_call.name, "remark");
+        assert_ne!(old_call.arguments[0].ty.id(), new_call.arguments[0].ty.id());
+
+        let old_hashes = metadata_hashes(&old).unwrap();
+        let new_hashes = metadata_hashes(&new).unwrap();
+
+        assert_eq!(old_hashes[0].calls[0], new_hashes[0].calls[0]);
+    }
+
+    #[test]
+    fn changed_metadata_is_incompatible() {
+        let expected = expected_hashes(&read_metadata("test-runtime-v14.scale"));
+
+        let result =
+            check_compatibility(&read_metadata("test-runtime-v14-upgrade.scale"), &expected);
+
+        let incompatibilities = match result {
+            Err(Incompatibilities::Changed(incompatibilities)) => incompatibilities,
+            other => panic!("expected incompatibilities, got {:?}", other),
+        };
+        assert_eq!(
+            incompatibilities,
+            [
+                Incompatibility::EventChanged {
+                    pallet: "System".into(),
+                    event: "ExtrinsicSuccess".into()
+                },
+                Incompatibility::EventChanged {
+                    pallet: "System".into(),
+                    event: "ExtrinsicFailed".into()
+                },
+                Incompatibility::StorageChanged {
+                    pallet: "System".into(),
+                    storage: "BlockHash".into()
+                },
+                Incompatibility::StorageChanged {
+                    pallet: "System".into(),
+                    storage: "Events".into()
+                },
+                Incompatibility::MissingStorage {
+                    pallet: "System".into(),
+                    storage: "EventTopics".into()
+                },
+                Incompatibility::MissingCall {
+                    pallet: "Balances".into(),
+                    call: "transfer".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn changed_pallets_with_unchanged_items_are_compatible() {
+        let metadata = read_metadata("test-runtime-v14.scale");
+        let mut expected = expected_hashes(&metadata);
+        // e.g. a constant of the pallet changed
+        expected[1].hash ^= 1;
+
+        assert!(check_compatibility(&metadata, &expected).is_ok());
+        assert_eq!(changed_pallets(&metadata, &expected).unwrap(), ["Balances"]);
+    }
+
+    #[test]
+    fn changed_events_are_incompatible() {
+        // only expect the events, so that the changed calls and storage entries aren't reported
+        let expected = expected_hashes(&read_metadata("test-runtime-v14.scale"))
+            .into_iter()
+            .map(|pallet| PalletHashes {
+                calls: &[],
+                storage: &[],
+                ..pallet
+            })
+            .collect::<Vec<_>>();
+
+        let result =
+            check_compatibility(&read_metadata("test-runtime-v14-upgrade.scale"), &expected);
+
+        let incompatibilities = match result {
+            Err(Incompatibilities::Changed(incompatibilities)) => incompatibilities,
+            other => panic!("expected incompatibilities, got {:?}", other),
+        };
+        // the upgrade adds a field to the `DispatchInfo` of `ExtrinsicSuccess`
+        assert!(incompatibilities.contains(&Incompatibility::EventChanged {
+            pallet: "System".into(),
+            event: "ExtrinsicSuccess".into(),
+        }));
+        assert!(!incompatibilities.iter().any(|incompatibility| matches!(
+            incompatibility,
+            Incompatibility::CallChanged { .. } | Incompatibility::StorageChanged { .. }
+        )));
+    }
+
+    #[test]
+    fn changed_errors_are_incompatible() {
+        let metadata = read_metadata("test-runtime-v14-indices.scale");
+        let mut expected = expected_hashes(&metadata);
+        let example = expected.iter_mut().find(|p| p.name == "Example").unwrap();
+        let mut errors = example.errors.to_vec();
+        errors[1].1 ^= 1;
+        errors.push(("Removed", 0));
+        example.errors = Box::leak(errors.into_boxed_slice());
+        example.hash ^= 1;
+
+        let result = check_compatibility(&metadata, &expected);
+
+        assert!(matches!(
+            result,
+            Err(Incompatibilities::Changed(ref incompatibilities))
+                if incompatibilities == &[
+                    Incompatibility::ErrorChanged {
+                        pallet: "Example".into(),
+                        error: "TooLong".into()
+                    },
+                    Incompatibility::MissingError {
+                        pallet: "Example".into(),
+                        error: "Removed".into()
+                    },
+                ]
+        ));
+    }
+
+    #[test]
+    fn changed_pallet_index_is_incompatible() {
+        let metadata = read_metadata("test-runtime-v14.scale");
+        let mut expected = expected_hashes(&metadata);
+        expected[1].index = 6;
+        expected[1].hash ^= 1;
+
+        let result = check_compatibility(&metadata, &expected);
+
+        assert!(matches!(
+            result,
+            Err(Incompatibilities::Changed(ref incompatibilities))
+                if incompatibilities == &[Incompatibility::PalletIndexChanged {
+                    pallet: "Balances".into()
+                }]
+        ));
+    }
+
+    #[test]
+    fn invalid_metadata_is_incompatible() {
+        let result = check_compatibility(b"not metadata", &[]);
+
+        assert!(matches!(result, Err(Incompatibilities::InvalidMetadata(_))));
+    }
+}
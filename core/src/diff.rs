@@ -0,0 +1,1080 @@
+//! Compare the metadata of two runtime versions, e.g. to review the changes of a runtime upgrade.
+//!
+//! Pallets, calls, events and storage entries are matched by name. Pallets, calls and events
+//! which are only found in one of the runtimes but share their index are reported as renamed.
+//! The types of matched items are compared by their shape rather than their type ids, which
+//! differ between the type registries of the two runtimes.
+
+use crate::{
+    generate_storage::storage_entry_types,
+    generate_types::{call_index, variant_index},
+    Error, Result,
+};
+use frame_metadata::v13::{
+    PalletMetadata, RuntimeMetadataV13, StorageEntryModifier, StorageHasher,
+};
+use scale_info::{
+    form::PortableForm, prelude::num::NonZeroU32, PortableRegistry, Type, TypeDef, Variant,
+};
+use serde::Serialize;
+use std::{collections::HashSet, fmt};
+
+/// Compare the metadata of two runtime versions, in any of the formats accepted by
+/// [`generate_runtime_types_from_bytes`](crate::generate_runtime_types_from_bytes).
+///
+/// Only V13 and V14 metadata can be compared, legacy V12 metadata has no type registry.
+pub fn diff_metadata(old: &[u8], new: &[u8]) -> Result<MetadataDiff> {
+    let old = crate::decode_metadata_v13(old)?;
+    let new = crate::decode_metadata_v13(new)?;
+    MetadataDiff::new(&old, &new)
+}
+
+/// The changes between the metadata of two runtime versions.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct MetadataDiff {
+    pub changes: Vec<Change>,
+}
+
+impl MetadataDiff {
+    pub fn new(old: &RuntimeMetadataV13, new: &RuntimeMetadataV13) -> Result<Self> {
+        let mut differ = Differ {
+            old_types: &old.types,
+            new_types: &new.types,
+            item: None,
+            visited: HashSet::new(),
+            changes: Vec::new(),
+        };
+        differ.diff_pallets(&old.pallets, &new.pallets)?;
+        Ok(Self {
+            changes: differ.changes,
+        })
+    }
+
+    /// Returns `true` if there are no changes between the two runtime versions.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for MetadataDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+/// A change to the pallets of a runtime, or to an item of a pallet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+    PalletAdded {
+        pallet: String,
+        index: u8,
+    },
+    PalletRemoved {
+        pallet: String,
+        index: u8,
+    },
+    PalletRenamed {
+        index: u8,
+        old: String,
+        new: String,
+    },
+    PalletIndexChanged {
+        pallet: String,
+        old: u8,
+        new: u8,
+    },
+    ItemAdded {
+        pallet: String,
+        item: ItemKind,
+        name: String,
+    },
+    ItemRemoved {
+        pallet: String,
+        item: ItemKind,
+        name: String,
+    },
+    ItemRenamed {
+        pallet: String,
+        item: ItemKind,
+        index: u8,
+        old: String,
+        new: String,
+    },
+    ItemIndexChanged {
+        pallet: String,
+        item: ItemKind,
+        name: String,
+        old: u8,
+        new: u8,
+    },
+    /// The hashers of the keys of a storage map changed, which changes its storage keys.
+    StorageHashersChanged {
+        pallet: String,
+        name: String,
+        old: Vec<String>,
+        new: Vec<String>,
+    },
+    /// A storage entry changed between returning an `Option` and a default value.
+    StorageModifierChanged {
+        pallet: String,
+        name: String,
+        old: String,
+        new: String,
+    },
+    /// The shape of a type used by an item changed, at the given path within the item, e.g.
+    /// `dest` for an argument of a call, or `value.data.free` for a field of a storage value.
+    TypeChanged {
+        pallet: String,
+        item: ItemKind,
+        name: String,
+        path: String,
+        type_change: TypeChange,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::PalletAdded { pallet, index } => {
+                write!(f, "+ pallet {} (index {})", pallet, index)
+            }
+            Self::PalletRemoved { pallet, index } => {
+                write!(f, "- pallet {} (index {})", pallet, index)
+            }
+            Self::PalletRenamed { index, old, new } => {
+                write!(f, "~ pallet {} renamed to {} (index {})", old, new, index)
+            }
+            Self::PalletIndexChanged { pallet, old, new } => {
+                write!(
+                    f,
+                    "~ pallet {} index changed from {} to {}",
+                    pallet, old, new
+                )
+            }
+            Self::ItemAdded { pallet, item, name } => write!(f, "+ {} {} {}", pallet, item, name),
+            Self::ItemRemoved { pallet, item, name } => write!(f, "- {} {} {}", pallet, item, name),
+            Self::ItemRenamed {
+                pallet,
+                item,
+                index,
+                old,
+                new,
+            } => write!(
+                f,
+                "~ {} {} {} renamed to {} (index {})",
+                pallet, item, old, new, index
+            ),
+            Self::ItemIndexChanged {
+                pallet,
+                item,
+                name,
+                old,
+                new,
+            } => write!(
+                f,
+                "~ {} {} {} index changed from {} to {}",
+                pallet, item, name, old, new
+            ),
+            Self::StorageHashersChanged {
+                pallet,
+                name,
+                old,
+                new,
+            } => write!(
+                f,
+                "~ {} storage {} hashers changed from [{}] to [{}]",
+                pallet,
+                name,
+                old.join(", "),
+                new.join(", ")
+            ),
+            Self::StorageModifierChanged {
+                pallet,
+                name,
+                old,
+                new,
+            } => write!(
+                f,
+                "~ {} storage {} modifier changed from {} to {}",
+                pallet, name, old, new
+            ),
+            Self::TypeChanged {
+                pallet,
+                item,
+                name,
+                path,
+                type_change,
+            } => write!(
+                f,
+                "~ {} {} {} `{}`: {}",
+                pallet, item, name, path, type_change
+            ),
+        }
+    }
+}
+
+/// The kind of a pallet item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemKind {
+    Call,
+    Event,
+    Storage,
+}
+
+impl fmt::Display for ItemKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Call => write!(f, "call"),
+            Self::Event => write!(f, "event"),
+            Self::Storage => write!(f, "storage"),
+        }
+    }
+}
+
+/// A change to the shape of a type, which changes its SCALE encoding.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TypeChange {
+    FieldAdded {
+        field: String,
+    },
+    FieldRemoved {
+        field: String,
+    },
+    FieldsReordered,
+    VariantAdded {
+        variant: String,
+    },
+    VariantRemoved {
+        variant: String,
+    },
+    VariantIndexChanged {
+        variant: String,
+        old: u8,
+        new: u8,
+    },
+    /// The type resolves to a different kind of type, e.g. `u32` instead of `u64`.
+    LayoutChanged {
+        old: String,
+        new: String,
+    },
+}
+
+impl fmt::Display for TypeChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::FieldAdded { field } => write!(f, "field `{}` added", field),
+            Self::FieldRemoved { field } => write!(f, "field `{}` removed", field),
+            Self::FieldsReordered => write!(f, "fields reordered"),
+            Self::VariantAdded { variant } => write!(f, "variant `{}` added", variant),
+            Self::VariantRemoved { variant } => write!(f, "variant `{}` removed", variant),
+            Self::VariantIndexChanged { variant, old, new } => write!(
+                f,
+                "variant `{}` index changed from {} to {}",
+                variant, old, new
+            ),
+            Self::LayoutChanged { old, new } => {
+                write!(f, "layout changed from `{}` to `{}`", old, new)
+            }
+        }
+    }
+}
+
+/// How the items of the old and the new metadata correspond to each other, as the positions of
+/// the items in their lists.
+#[derive(Debug, PartialEq)]
+enum Matched {
+    Same(usize, usize),
+    Renamed(usize, usize),
+    Removed(usize),
+    Added(usize),
+}
+
+/// Match the items with the same name, then the remaining items with the same index as renamed.
+///
+/// Items without an index, such as storage entries, are never considered renamed.
+fn match_items(old: &[(Option<u8>, &str)], new: &[(Option<u8>, &str)]) -> Vec<Matched> {
+    let mut unmatched_new = (0..new.len())
+        .filter(|&j| !old.iter().any(|(_, name)| *name == new[j].1))
+        .collect::<Vec<_>>();
+    let mut matched = Vec::new();
+    for (i, (index, name)) in old.iter().enumerate() {
+        if let Some(j) = new.iter().position(|(_, new_name)| new_name == name) {
+            matched.push(Matched::Same(i, j));
+        } else if let Some(pos) = unmatched_new
+            .iter()
+            .position(|&j| index.is_some() && new[j].0 == *index)
+        {
+            matched.push(Matched::Renamed(i, unmatched_new.remove(pos)));
+        } else {
+            matched.push(Matched::Removed(i));
+        }
+    }
+    matched.extend(unmatched_new.into_iter().map(Matched::Added));
+    matched
+}
+
+/// A field of a type, a call argument or a storage key, to be compared with its counterpart.
+type FieldRef<'a> = (Option<&'a str>, NonZeroU32);
+
+/// The index, name and fields of a call, event or storage entry.
+type Item<'a> = (Option<u8>, &'a str, Vec<FieldRef<'a>>);
+
+struct Differ<'a> {
+    old_types: &'a PortableRegistry,
+    new_types: &'a PortableRegistry,
+    /// The pallet, kind and name of the item of which the types are being compared.
+    item: Option<(String, ItemKind, String)>,
+    /// The pairs of types already compared for the current item, to stop at recursive types.
+    visited: HashSet<(NonZeroU32, NonZeroU32)>,
+    changes: Vec<Change>,
+}
+
+impl<'a> Differ<'a> {
+    fn diff_pallets(
+        &mut self,
+        old: &'a [PalletMetadata<PortableForm>],
+        new: &'a [PalletMetadata<PortableForm>],
+    ) -> Result<()> {
+        let old_keys = old
+            .iter()
+            .map(|p| (Some(p.index), p.name.as_str()))
+            .collect::<Vec<_>>();
+        let new_keys = new
+            .iter()
+            .map(|p| (Some(p.index), p.name.as_str()))
+            .collect::<Vec<_>>();
+        for matched in match_items(&old_keys, &new_keys) {
+            match matched {
+                Matched::Same(i, j) => {
+                    if old[i].index != new[j].index {
+                        self.changes.push(Change::PalletIndexChanged {
+                            pallet: new[j].name.clone(),
+                            old: old[i].index,
+                            new: new[j].index,
+                        });
+                    }
+                    self.diff_pallet(&old[i], &new[j])?;
+                }
+                Matched::Renamed(i, j) => {
+                    self.changes.push(Change::PalletRenamed {
+                        index: new[j].index,
+                        old: old[i].name.clone(),
+                        new: new[j].name.clone(),
+                    });
+                    self.diff_pallet(&old[i], &new[j])?;
+                }
+                Matched::Removed(i) => self.changes.push(Change::PalletRemoved {
+                    pallet: old[i].name.clone(),
+                    index: old[i].index,
+                }),
+                Matched::Added(j) => self.changes.push(Change::PalletAdded {
+                    pallet: new[j].name.clone(),
+                    index: new[j].index,
+                }),
+            }
+        }
+        Ok(())
+    }
+
+    fn diff_pallet(
+        &mut self,
+        old: &'a PalletMetadata<PortableForm>,
+        new: &'a PalletMetadata<PortableForm>,
+    ) -> Result<()> {
+        let pallet = &new.name;
+
+        let calls = |types: &PortableRegistry, pallet: &'a PalletMetadata<PortableForm>| {
+            pallet
+                .calls
+                .iter()
+                .flat_map(|calls| calls.calls.iter().map(move |call| (calls, call)))
+                .map(|(calls, call)| {
+                    let args = call
+                        .arguments
+                        .iter()
+                        .map(|arg| (Some(arg.name.as_str()), arg.ty.id()))
+                        .collect();
+                    let index = call_index(types, calls, &call.name)?;
+                    Ok((Some(index), call.name.as_str(), args))
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+        let old_calls = calls(self.old_types, old)?;
+        let new_calls = calls(self.new_types, new)?;
+        self.diff_items(pallet, ItemKind::Call, old_calls, new_calls)?;
+
+        let old_events = self.events(self.old_types, old)?;
+        let new_events = self.events(self.new_types, new)?;
+        self.diff_items(pallet, ItemKind::Event, old_events, new_events)?;
+
+        self.diff_storage_entries(old, new);
+        let old_storage = self.storage(self.old_types, old);
+        let new_storage = self.storage(self.new_types, new);
+        self.diff_items(pallet, ItemKind::Storage, old_storage, new_storage)
+    }
+
+    /// Compare the hashers and modifiers of the storage entries with the same name, which change
+    /// the storage keys and the decoding of missing values.
+    fn diff_storage_entries(
+        &mut self,
+        old: &'a PalletMetadata<PortableForm>,
+        new: &'a PalletMetadata<PortableForm>,
+    ) {
+        let entries = |pallet: &'a PalletMetadata<PortableForm>| {
+            pallet
+                .storage
+                .iter()
+                .flat_map(|storage| storage.entries.iter())
+        };
+        for new_entry in entries(new) {
+            let old_entry = match entries(old).find(|entry| entry.name == new_entry.name) {
+                Some(old_entry) => old_entry,
+                None => continue,
+            };
+            let (_, old_hashers, _) = storage_entry_types(self.old_types, old_entry);
+            let (_, new_hashers, _) = storage_entry_types(self.new_types, new_entry);
+            let old_hashers = old_hashers.into_iter().map(hasher_name).collect::<Vec<_>>();
+            let new_hashers = new_hashers.into_iter().map(hasher_name).collect::<Vec<_>>();
+            if old_hashers != new_hashers {
+                self.changes.push(Change::StorageHashersChanged {
+                    pallet: new.name.clone(),
+                    name: new_entry.name.clone(),
+                    old: old_hashers,
+                    new: new_hashers,
+                });
+            }
+            let old_modifier = modifier_name(&old_entry.modifier);
+            let new_modifier = modifier_name(&new_entry.modifier);
+            if old_modifier != new_modifier {
+                self.changes.push(Change::StorageModifierChanged {
+                    pallet: new.name.clone(),
+                    name: new_entry.name.clone(),
+                    old: old_modifier,
+                    new: new_modifier,
+                });
+            }
+        }
+    }
+
+    /// Returns the index, name and fields of each event of the pallet.
+    fn events(
+        &self,
+        types: &'a PortableRegistry,
+        pallet: &PalletMetadata<PortableForm>,
+    ) -> Result<Vec<Item<'a>>> {
+        let event = match pallet.event {
+            Some(ref event) => event,
+            None => return Ok(Vec::new()),
+        };
+        let variants = match resolve(types, event.ty.id())?.type_def() {
+            TypeDef::Variant(variant) => variant.variants(),
+            _ => {
+                return Err(Error::ExpectedVariantType {
+                    id: event.ty.id().get(),
+                })
+            }
+        };
+        variant_items(variants)
+    }
+
+    /// Returns the name and the types of the keys and value of each storage entry of the pallet.
+    ///
+    /// The keys are named `key` if there is a single key, or `key1`, `key2`, .. otherwise.
+    fn storage(
+        &self,
+        types: &'a PortableRegistry,
+        pallet: &'a PalletMetadata<PortableForm>,
+    ) -> Vec<Item<'a>> {
+        const KEYS: [&str; 8] = [
+            "key1", "key2", "key3", "key4", "key5", "key6", "key7", "key8",
+        ];
+        pallet
+            .storage
+            .iter()
+            .flat_map(|storage| storage.entries.iter())
+            .map(|entry| {
+                let (keys, _, value) = storage_entry_types(types, entry);
+                let key_names = if keys.len() == 1 {
+                    &["key"][..]
+                } else {
+                    &KEYS[..]
+                };
+                let mut fields = keys
+                    .iter()
+                    .enumerate()
+                    .map(|(i, key)| (key_names.get(i).copied(), *key))
+                    .collect::<Vec<_>>();
+                fields.push((Some("value"), value));
+                (None, entry.name.as_str(), fields)
+            })
+            .collect()
+    }
+
+    fn diff_items(
+        &mut self,
+        pallet: &str,
+        item: ItemKind,
+        old: Vec<Item>,
+        new: Vec<Item>,
+    ) -> Result<()> {
+        let old_keys = old
+            .iter()
+            .map(|(i, name, _)| (*i, *name))
+            .collect::<Vec<_>>();
+        let new_keys = new
+            .iter()
+            .map(|(i, name, _)| (*i, *name))
+            .collect::<Vec<_>>();
+        for matched in match_items(&old_keys, &new_keys) {
+            let (i, j) = match matched {
+                Matched::Same(i, j) => {
+                    if let (Some(old_index), Some(new_index)) = (old[i].0, new[j].0) {
+                        if old_index != new_index {
+                            self.changes.push(Change::ItemIndexChanged {
+                                pallet: pallet.to_string(),
+                                item,
+                                name: new[j].1.to_string(),
+                                old: old_index,
+                                new: new_index,
+                            });
+                        }
+                    }
+                    (i, j)
+                }
+                Matched::Renamed(i, j) => {
+                    self.changes.push(Change::ItemRenamed {
+                        pallet: pallet.to_string(),
+                        item,
+                        index: new[j].0.unwrap_or_default(),
+                        old: old[i].1.to_string(),
+                        new: new[j].1.to_string(),
+                    });
+                    (i, j)
+                }
+                Matched::Removed(i) => {
+                    self.changes.push(Change::ItemRemoved {
+                        pallet: pallet.to_string(),
+                        item,
+                        name: old[i].1.to_string(),
+                    });
+                    continue;
+                }
+                Matched::Added(j) => {
+                    self.changes.push(Change::ItemAdded {
+                        pallet: pallet.to_string(),
+                        item,
+                        name: new[j].1.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            self.item = Some((pallet.to_string(), item, new[j].1.to_string()));
+            self.visited.clear();
+            self.diff_fields("", &old[i].2, &new[j].2)?;
+        }
+        Ok(())
+    }
+
+    fn diff_fields(&mut self, path: &str, old: &[FieldRef], new: &[FieldRef]) -> Result<()> {
+        // fields are matched by name, unless any of them are unnamed
+        let unnamed = |fields: &[FieldRef]| fields.iter().any(|(name, _)| name.is_none());
+        if !unnamed(old) && !unnamed(new) {
+            let find =
+                |fields: &[FieldRef], name| fields.iter().find(|f| f.0 == name).map(|(_, ty)| *ty);
+            let old_order = old
+                .iter()
+                .filter(|(name, _)| find(new, *name).is_some())
+                .map(|(name, _)| *name);
+            let new_order = new
+                .iter()
+                .filter(|(name, _)| find(old, *name).is_some())
+                .map(|(name, _)| *name);
+            if !old_order.eq(new_order) {
+                self.type_changed(path, TypeChange::FieldsReordered);
+            }
+            for (name, old_ty) in old {
+                let field = name.unwrap_or_default();
+                match find(new, *name) {
+                    Some(new_ty) => self.diff_types(&join(path, ".", field), *old_ty, new_ty)?,
+                    None => self.type_changed(
+                        path,
+                        TypeChange::FieldRemoved {
+                            field: field.to_string(),
+                        },
+                    ),
+                }
+            }
+            for (name, _) in new.iter().filter(|(name, _)| find(old, *name).is_none()) {
+                let field = name.unwrap_or_default().to_string();
+                self.type_changed(path, TypeChange::FieldAdded { field });
+            }
+        } else {
+            for (i, ((_, old_ty), (_, new_ty))) in old.iter().zip(new).enumerate() {
+                self.diff_types(&join(path, ".", &i.to_string()), *old_ty, *new_ty)?;
+            }
+            for i in new.len()..old.len() {
+                let field = i.to_string();
+                self.type_changed(path, TypeChange::FieldRemoved { field });
+            }
+            for i in old.len()..new.len() {
+                let field = i.to_string();
+                self.type_changed(path, TypeChange::FieldAdded { field });
+            }
+        }
+        Ok(())
+    }
+
+    fn diff_variants(
+        &mut self,
+        path: &str,
+        old: &'a [Variant<PortableForm>],
+        new: &'a [Variant<PortableForm>],
+    ) -> Result<()> {
+        let find = |variants: &'a [Variant<PortableForm>], name: &str| {
+            variants.iter().enumerate().find(|(_, v)| v.name() == name)
+        };
+        for (position, old_variant) in old.iter().enumerate() {
+            let name = old_variant.name();
+            let (new_index, new_variant) = match find(new, name) {
+                Some((new_position, new_variant)) => {
+                    (variant_index(new_position, new_variant)?, new_variant)
+                }
+                None => {
+                    let variant = name.to_string();
+                    self.type_changed(path, TypeChange::VariantRemoved { variant });
+                    continue;
+                }
+            };
+            let old_index = variant_index(position, old_variant)?;
+            if old_index != new_index {
+                let change = TypeChange::VariantIndexChanged {
+                    variant: name.to_string(),
+                    old: old_index,
+                    new: new_index,
+                };
+                self.type_changed(path, change);
+            }
+            self.diff_fields(
+                &join(path, "::", name),
+                &fields(old_variant.fields()),
+                &fields(new_variant.fields()),
+            )?;
+        }
+        for variant in new.iter().filter(|v| find(old, v.name()).is_none()) {
+            let variant = variant.name().to_string();
+            self.type_changed(path, TypeChange::VariantAdded { variant });
+        }
+        Ok(())
+    }
+
+    fn diff_types(&mut self, path: &str, old: NonZeroU32, new: NonZeroU32) -> Result<()> {
+        if !self.visited.insert((old, new)) {
+            return Ok(());
+        }
+        let old_ty = resolve(self.old_types, old)?;
+        let new_ty = resolve(self.new_types, new)?;
+        match (old_ty.type_def(), new_ty.type_def()) {
+            (TypeDef::Composite(old), TypeDef::Composite(new)) => {
+                self.diff_fields(path, &fields(old.fields()), &fields(new.fields()))
+            }
+            (TypeDef::Variant(old), TypeDef::Variant(new)) => {
+                self.diff_variants(path, old.variants(), new.variants())
+            }
+            (TypeDef::Sequence(old), TypeDef::Sequence(new)) => self.diff_types(
+                &format!("{}[]", path),
+                old.type_param().id(),
+                new.type_param().id(),
+            ),
+            (TypeDef::Array(old), TypeDef::Array(new)) if old.len() == new.len() => self
+                .diff_types(
+                    &format!("{}[]", path),
+                    old.type_param().id(),
+                    new.type_param().id(),
+                ),
+            (TypeDef::Tuple(old), TypeDef::Tuple(new))
+                if old.fields().len() == new.fields().len() =>
+            {
+                for (i, (old, new)) in old.fields().iter().zip(new.fields()).enumerate() {
+                    self.diff_types(&join(path, ".", &i.to_string()), old.id(), new.id())?;
+                }
+                Ok(())
+            }
+            (TypeDef::Compact(old), TypeDef::Compact(new)) => {
+                self.diff_types(path, old.type_param().id(), new.type_param().id())
+            }
+            (TypeDef::Primitive(old), TypeDef::Primitive(new)) if old == new => Ok(()),
+            (TypeDef::Phantom(_), TypeDef::Phantom(_)) => Ok(()),
+            _ => {
+                let change = TypeChange::LayoutChanged {
+                    old: describe(old_ty),
+                    new: describe(new_ty),
+                };
+                self.type_changed(path, change);
+                Ok(())
+            }
+        }
+    }
+
+    fn type_changed(&mut self, path: &str, type_change: TypeChange) {
+        let (pallet, item, name) = self
+            .item
+            .clone()
+            .expect("types are only compared for an item");
+        self.changes.push(Change::TypeChanged {
+            pallet,
+            item,
+            name,
+            path: path.to_string(),
+            type_change,
+        });
+    }
+}
+
+fn resolve(types: &PortableRegistry, id: NonZeroU32) -> Result<&Type<PortableForm>> {
+    types
+        .resolve(id)
+        .ok_or(Error::TypeNotFound { id: id.get() })
+}
+
+fn fields(fields: &[scale_info::Field<PortableForm>]) -> Vec<FieldRef<'_>> {
+    fields
+        .iter()
+        .map(|f| (f.name().map(|name| name.as_str()), f.ty().id()))
+        .collect()
+}
+
+fn join(path: &str, separator: &str, segment: &str) -> String {
+    if path.is_empty() {
+        segment.to_string()
+    } else {
+        format!("{}{}{}", path, separator, segment)
+    }
+}
+
+/// A short description of a type for reporting a changed layout, e.g. `u32` or the path of a
+/// composite type.
+fn describe(ty: &Type<PortableForm>) -> String {
+    match ty.type_def() {
+        TypeDef::Composite(_) | TypeDef::Variant(_) if !ty.path().segments().is_empty() => {
+            ty.path().segments().join("::")
+        }
+        TypeDef::Composite(_) => "struct".to_string(),
+        TypeDef::Variant(_) => "enum".to_string(),
+        TypeDef::Sequence(_) => "sequence".to_string(),
+        TypeDef::Array(array) => format!("array of {}", array.len()),
+        TypeDef::Tuple(tuple) => format!("tuple of {}", tuple.fields().len()),
+        TypeDef::Primitive(primitive) => format!("{:?}", primitive).to_lowercase(),
+        TypeDef::Compact(_) => "compact".to_string(),
+        TypeDef::Phantom(_) => "phantom".to_string(),
+    }
+}
+
+/// Returns the index, name and fields of each variant. The position skips the `__Ignore` marker
+/// variant of V13 metadata, which isn't counted by the runtime.
+fn variant_items(variants: &[Variant<PortableForm>]) -> Result<Vec<Item<'_>>> {
+    variants
+        .iter()
+        .filter(|v| v.name() != "__Ignore")
+        .enumerate()
+        .map(|(position, v)| {
+            let fields = v
+                .fields()
+                .iter()
+                .map(|f| (f.name().map(|name| name.as_str()), f.ty().id()))
+                .collect();
+            Ok((Some(variant_index(position, v)?), v.name().as_str(), fields))
+        })
+        .collect()
+}
+
+fn hasher_name(hasher: &StorageHasher) -> String {
+    match hasher {
+        StorageHasher::Blake2_128 => "Blake2_128",
+        StorageHasher::Blake2_256 => "Blake2_256",
+        StorageHasher::Blake2_128Concat => "Blake2_128Concat",
+        StorageHasher::Twox128 => "Twox128",
+        StorageHasher::Twox256 => "Twox256",
+        StorageHasher::Twox64Concat => "Twox64Concat",
+        StorageHasher::Identity => "Identity",
+    }
+    .to_string()
+}
+
+fn modifier_name(modifier: &StorageEntryModifier) -> String {
+    match modifier {
+        StorageEntryModifier::Optional => "optional",
+        StorageEntryModifier::Default => "default",
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::read_metadata;
+    use frame_metadata::v13::StorageEntryType;
+
+    fn test_runtime_upgrade_diff() -> MetadataDiff {
+        diff_metadata(
+            &read_metadata("test-runtime-v14.scale"),
+            &read_metadata("test-runtime-v14-upgrade.scale"),
+        )
+        .unwrap()
+    }
+
+    fn type_changed(
+        pallet: &str,
+        item: ItemKind,
+        name: &str,
+        path: &str,
+        type_change: TypeChange,
+    ) -> Change {
+        Change::TypeChanged {
+            pallet: pallet.into(),
+            item,
+            name: name.into(),
+            path: path.into(),
+            type_change,
+        }
+    }
+
+    #[test]
+    fn same_metadata_has_no_changes() {
+        for file in &["node-runtime.scale", "test-runtime-v14-indices.scale"] {
+            let metadata = read_metadata(file);
+
+            let diff = diff_metadata(&metadata, &metadata).unwrap();
+
+            assert!(diff.is_empty(), "{}", diff);
+        }
+    }
+
+    #[test]
+    fn variant_positions_skip_ignore() {
+        let metadata = crate::decode_metadata_v13(&read_metadata("node-runtime.scale")).unwrap();
+        let balances = metadata
+            .pallets
+            .iter()
+            .find(|pallet| pallet.name == "Balances")
+            .unwrap();
+        let variants = match resolve(&metadata.types, balances.error.as_ref().unwrap().ty.id())
+            .unwrap()
+            .type_def()
+        {
+            TypeDef::Variant(variant) => variant.variants(),
+            _ => panic!("the error is not a variant"),
+        };
+        assert_eq!(variants[0].name(), "__Ignore");
+
+        let items = variant_items(variants).unwrap();
+
+        assert_eq!(items.len(), variants.len() - 1);
+        assert_eq!((items[0].0, items[0].1), (Some(0), "VestingBalance"));
+        assert_eq!((items[1].0, items[1].1), (Some(1), "LiquidityRestrictions"));
+    }
+
+    #[test]
+    fn match_items_by_name_then_index() {
+        let old = [(Some(0), "a"), (Some(1), "b"), (Some(2), "c"), (None, "d")];
+        let new = [(Some(0), "a"), (Some(1), "e"), (Some(3), "c"), (None, "f")];
+
+        assert_eq!(
+            match_items(&old, &new),
+            [
+                Matched::Same(0, 0),
+                Matched::Renamed(1, 1),
+                Matched::Same(2, 2),
+                Matched::Removed(3),
+                Matched::Added(3),
+            ]
+        );
+    }
+
+    #[test]
+    fn added_removed_and_renamed_items() {
+        let diff = test_runtime_upgrade_diff();
+
+        let expected = [
+            Change::PalletAdded {
+                pallet: "Timestamp".into(),
+                index: 3,
+            },
+            Change::ItemRenamed {
+                pallet: "Balances".into(),
+                item: ItemKind::Call,
+                index: 0,
+                old: "transfer".into(),
+                new: "transfer_allow_death".into(),
+            },
+            Change::ItemAdded {
+                pallet: "Balances".into(),
+                item: ItemKind::Call,
+                name: "transfer_all".into(),
+            },
+            Change::ItemAdded {
+                pallet: "Balances".into(),
+                item: ItemKind::Event,
+                name: "Deposit".into(),
+            },
+            Change::ItemAdded {
+                pallet: "System".into(),
+                item: ItemKind::Event,
+                name: "CodeUpdated".into(),
+            },
+            Change::ItemIndexChanged {
+                pallet: "System".into(),
+                item: ItemKind::Event,
+                name: "ExtrinsicSuccess".into(),
+                old: 0,
+                new: 1,
+            },
+            Change::ItemRemoved {
+                pallet: "System".into(),
+                item: ItemKind::Storage,
+                name: "EventTopics".into(),
+            },
+            Change::ItemAdded {
+                pallet: "Balances".into(),
+                item: ItemKind::Storage,
+                name: "InactiveIssuance".into(),
+            },
+        ];
+        for change in &expected {
+            assert!(
+                diff.changes.contains(change),
+                "missing `{}` in\n{}",
+                change,
+                diff
+            );
+        }
+    }
+
+    #[test]
+    fn type_shape_changes() {
+        let diff = test_runtime_upgrade_diff();
+
+        let expected = [
+            type_changed(
+                "System",
+                ItemKind::Event,
+                "ExtrinsicSuccess",
+                "dispatch_info",
+                TypeChange::FieldAdded {
+                    field: "class".into(),
+                },
+            ),
+            type_changed(
+                "System",
+                ItemKind::Event,
+                "ExtrinsicFailed",
+                "dispatch_error",
+                TypeChange::VariantIndexChanged {
+                    variant: "Module".into(),
+                    old: 3,
+                    new: 4,
+                },
+            ),
+            type_changed(
+                "System",
+                ItemKind::Storage,
+                "BlockHash",
+                "key",
+                TypeChange::LayoutChanged {
+                    old: "u32".into(),
+                    new: "u64".into(),
+                },
+            ),
+        ];
+        for change in &expected {
+            assert!(
+                diff.changes.contains(change),
+                "missing `{}` in\n{}",
+                change,
+                diff
+            );
+        }
+        // unchanged calls are not reported
+        assert!(!diff.changes.iter().any(|change| matches!(
+            change,
+            Change::TypeChanged { name, .. } if name == "transfer_keep_alive"
+        )));
+    }
+
+    #[test]
+    fn storage_hasher_and_modifier_changes() {
+        let old = crate::decode_metadata_v13(&read_metadata("test-runtime-v14.scale")).unwrap();
+        let mut new = old.clone();
+        let entry = new
+            .pallets
+            .iter_mut()
+            .filter(|pallet| pallet.name == "System")
+            .flat_map(|pallet| pallet.storage.iter_mut())
+            .flat_map(|storage| storage.entries.iter_mut())
+            .find(|entry| entry.name == "BlockHash")
+            .unwrap();
+        entry.modifier = StorageEntryModifier::Optional;
+        match entry.ty {
+            StorageEntryType::Map { ref mut hasher, .. } => *hasher = StorageHasher::Identity,
+            _ => panic!("BlockHash is not a map"),
+        }
+
+        let diff = MetadataDiff::new(&old, &new).unwrap();
+
+        assert_eq!(
+            diff.changes,
+            [
+                Change::StorageHashersChanged {
+                    pallet: "System".into(),
+                    name: "BlockHash".into(),
+                    old: vec!["Twox64Concat".into()],
+                    new: vec!["Identity".into()],
+                },
+                Change::StorageModifierChanged {
+                    pallet: "System".into(),
+                    name: "BlockHash".into(),
+                    old: "default".into(),
+                    new: "optional".into(),
+                },
+            ]
+        );
+        assert_eq!(
+            diff.changes[0].to_string(),
+            "~ System storage BlockHash hashers changed from [Twox64Concat] to [Identity]"
+        );
+    }
+
+    #[test]
+    fn diff_as_json() {
+        let change = type_changed(
+            "System",
+            ItemKind::Storage,
+            "BlockHash",
+            "key",
+            TypeChange::LayoutChanged {
+                old: "u32".into(),
+                new: "u64".into(),
+            },
+        );
+
+        let json = serde_json::to_value(&change).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "change": "type_changed",
+                "pallet": "System",
+                "item": "storage",
+                "name": "BlockHash",
+                "path": "key",
+                "type_change": { "kind": "layout_changed", "old": "u32", "new": "u64" },
+            })
+        );
+    }
+}
@@ -0,0 +1,618 @@
+//! Decoding and encoding SCALE values of any type in the type registry of the runtime metadata,
+//! without generating code for the types first.
+//!
+//! A [`Value`] describes itself: composites keep their field names and variants their names, so a
+//! value decoded from a type can be inspected, printed or modified and then encoded back with the
+//! same type id. Type ids are resolved with the same rules as the [`TypeGenerator`]: a `Cow<T>` is
+//! encoded as its `T`, and the index of a variant is its discriminant if it has one, or else its
+//! position.
+//!
+//! The prototype type registry has no type definition for bit sequences, so a `bitvec::vec::BitVec`
+//! type is recognized by its path and decoded as a [`Value::BitSequence`], using its store and order
+//! type parameters.
+//!
+//! [`TypeGenerator`]: crate::TypeGenerator
+
+use crate::{
+    generate_types::{resolve_type, variant_index},
+    Error, Result,
+};
+use scale::{Compact, Decode, Encode};
+use scale_info::{
+    form::PortableForm, prelude::num::NonZeroU32, Field, PortableRegistry, Type, TypeDef,
+    TypeDefPrimitive,
+};
+
+/// A SCALE value of a type in the type registry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    /// A struct, or a composite without fields such as `()` structs.
+    Composite(Composite),
+    /// A variant of an enum, with its index as encoded.
+    Variant {
+        name: String,
+        index: u8,
+        fields: Composite,
+    },
+    /// The elements of a sequence, or of a fixed size array.
+    Sequence(Vec<Value>),
+    Tuple(Vec<Value>),
+    Primitive(Primitive),
+    BitSequence(Vec<bool>),
+}
+
+/// The fields of a struct or variant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Composite {
+    Named(Vec<(String, Value)>),
+    Unnamed(Vec<Value>),
+}
+
+/// A primitive value, 256 bit integers are kept as their little endian bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Primitive {
+    Bool(bool),
+    Char(char),
+    Str(String),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    U128(u128),
+    U256([u8; 32]),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    I128(i128),
+    I256([u8; 32]),
+}
+
+impl Value {
+    /// Decode a value of the type with the given id, advancing the input past the decoded bytes.
+    pub fn decode(types: &PortableRegistry, id: NonZeroU32, input: &mut &[u8]) -> Result<Self> {
+        ValueDecoder { types }.decode(id, input)
+    }
+
+    /// Encode the value as the type with the given id. Variants are looked up by name, so the
+    /// index of a [`Value::Variant`] is ignored.
+    ///
+    /// # Errors
+    ///
+    /// If the value doesn't have the shape of the type, e.g. a field or variant is missing.
+    pub fn encode(&self, types: &PortableRegistry, id: NonZeroU32) -> Result<Vec<u8>> {
+        let mut output = Vec::new();
+        self.encode_to(types, id, &mut output)?;
+        Ok(output)
+    }
+
+    /// Encode the value as the type with the given id, appending the bytes to the output.
+    pub fn encode_to(
+        &self,
+        types: &PortableRegistry,
+        id: NonZeroU32,
+        output: &mut Vec<u8>,
+    ) -> Result<()> {
+        ValueEncoder { types }.encode(id, self, output)
+    }
+}
+
+/// The layout of a `bitvec::vec::BitVec<O, T>`: the number of bits in each element of the store
+/// type `T`, and whether the bits of an element are in `Msb0` order.
+fn bit_sequence_layout(
+    types: &PortableRegistry,
+    id: NonZeroU32,
+    ty: &Type<PortableForm>,
+) -> Result<Option<(usize, bool)>> {
+    if ty.path().segments() != ["bitvec", "vec", "BitVec"] {
+        return Ok(None);
+    }
+    let mut store_bits = None;
+    let mut msb0 = false;
+    for param in ty.type_params() {
+        let (_, param) = resolve_type(types, param.id())?;
+        match param.type_def() {
+            TypeDef::Primitive(TypeDefPrimitive::U8) => store_bits = Some(8),
+            TypeDef::Primitive(TypeDefPrimitive::U16) => store_bits = Some(16),
+            TypeDef::Primitive(TypeDefPrimitive::U32) => store_bits = Some(32),
+            TypeDef::Primitive(TypeDefPrimitive::U64) => store_bits = Some(64),
+            _ => msb0 |= param.path().ident() == Some("Msb0".to_string()),
+        }
+    }
+    let store_bits = store_bits.ok_or(Error::UnsupportedDynamicType {
+        id: id.get(),
+        reason: "the store type of a bit sequence must be an unsigned integer",
+    })?;
+    Ok(Some((store_bits, msb0)))
+}
+
+/// The position of the bit with the given index in its store element.
+fn bit_shift(index: usize, store_bits: usize, msb0: bool) -> usize {
+    if msb0 {
+        store_bits - 1 - index % store_bits
+    } else {
+        index % store_bits
+    }
+}
+
+fn decode_bits(input: &mut &[u8], store_bits: usize, msb0: bool) -> Result<Vec<bool>> {
+    let len = Compact::<u32>::decode(input)?.0 as usize;
+    let store_bytes = store_bits / 8;
+    let elements = len.div_ceil(store_bits);
+    if input.len() < elements * store_bytes {
+        return Err(scale::Error::from("Not enough data to decode the bit sequence").into());
+    }
+    let (bytes, rest) = input.split_at(elements * store_bytes);
+    *input = rest;
+
+    let elements = bytes
+        .chunks(store_bytes)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .rev()
+                .fold(0u64, |element, byte| (element << 8) | u64::from(*byte))
+        })
+        .collect::<Vec<_>>();
+    Ok((0..len)
+        .map(|i| (elements[i / store_bits] >> bit_shift(i, store_bits, msb0)) & 1 == 1)
+        .collect())
+}
+
+fn encode_bits(bits: &[bool], store_bits: usize, msb0: bool, output: &mut Vec<u8>) {
+    Compact(bits.len() as u32).encode_to(output);
+    let mut elements = vec![0u64; bits.len().div_ceil(store_bits)];
+    for (i, _) in bits.iter().enumerate().filter(|(_, bit)| **bit) {
+        elements[i / store_bits] |= 1 << bit_shift(i, store_bits, msb0);
+    }
+    for element in elements {
+        output.extend_from_slice(&element.to_le_bytes()[..store_bits / 8]);
+    }
+}
+
+struct ValueDecoder<'a> {
+    types: &'a PortableRegistry,
+}
+
+impl ValueDecoder<'_> {
+    fn decode(&self, id: NonZeroU32, input: &mut &[u8]) -> Result<Value> {
+        let (id, ty) = resolve_type(self.types, id)?;
+        if let Some((store_bits, msb0)) = bit_sequence_layout(self.types, id, ty)? {
+            return Ok(Value::BitSequence(decode_bits(input, store_bits, msb0)?));
+        }
+
+        let value = match ty.type_def() {
+            TypeDef::Composite(composite) => {
+                Value::Composite(self.decode_fields(ty, composite.fields(), input)?)
+            }
+            TypeDef::Variant(variant) => {
+                let index = u8::decode(input)?;
+                let v = variant
+                    .variants()
+                    .iter()
+                    .enumerate()
+                    .find(|(position, v)| variant_index(*position, v).ok() == Some(index))
+                    .map(|(_, v)| v)
+                    .ok_or(Error::UnknownVariant {
+                        id: id.get(),
+                        variant: index.to_string(),
+                    })?;
+                Value::Variant {
+                    name: v.name().clone(),
+                    index,
+                    fields: self.decode_fields(ty, v.fields(), input)?,
+                }
+            }
+            TypeDef::Sequence(sequence) => {
+                let len = Compact::<u32>::decode(input)?.0;
+                let elements = (0..len)
+                    .map(|_| self.decode(sequence.type_param().id(), input))
+                    .collect::<Result<_>>()?;
+                Value::Sequence(elements)
+            }
+            TypeDef::Array(array) => {
+                let elements = (0..array.len())
+                    .map(|_| self.decode(array.type_param().id(), input))
+                    .collect::<Result<_>>()?;
+                Value::Sequence(elements)
+            }
+            TypeDef::Tuple(tuple) => {
+                let elements = tuple
+                    .fields()
+                    .iter()
+                    .map(|field| self.decode(field.id(), input))
+                    .collect::<Result<_>>()?;
+                Value::Tuple(elements)
+            }
+            TypeDef::Primitive(primitive) => Value::Primitive(decode_primitive(primitive, input)?),
+            TypeDef::Compact(compact) => self.decode_compact(compact.type_param().id(), input)?,
+            // phantom data is not encoded
+            TypeDef::Phantom(_) => Value::Tuple(Vec::new()),
+        };
+        Ok(value)
+    }
+
+    fn decode_fields(
+        &self,
+        ty: &Type<PortableForm>,
+        fields: &[Field<PortableForm>],
+        input: &mut &[u8],
+    ) -> Result<Composite> {
+        if fields.iter().all(|f| f.name().is_none()) {
+            let values = fields
+                .iter()
+                .map(|f| self.decode(f.ty().id(), input))
+                .collect::<Result<_>>()?;
+            Ok(Composite::Unnamed(values))
+        } else if let Some(names) = field_names(fields) {
+            let values = names
+                .into_iter()
+                .zip(fields)
+                .map(|(name, f)| Ok((name.clone(), self.decode(f.ty().id(), input)?)))
+                .collect::<Result<_>>()?;
+            Ok(Composite::Named(values))
+        } else {
+            Err(Error::MixedFieldNames {
+                type_path: format!("{:?}", ty.path()),
+            })
+        }
+    }
+
+    /// Decode a compact encoded integer, or a struct with a single field which is encoded as a
+    /// compact integer.
+    fn decode_compact(&self, id: NonZeroU32, input: &mut &[u8]) -> Result<Value> {
+        let (id, ty) = resolve_type(self.types, id)?;
+        let primitive = match ty.type_def() {
+            TypeDef::Primitive(TypeDefPrimitive::U8) => {
+                Primitive::U8(Compact::<u8>::decode(input)?.0)
+            }
+            TypeDef::Primitive(TypeDefPrimitive::U16) => {
+                Primitive::U16(Compact::<u16>::decode(input)?.0)
+            }
+            TypeDef::Primitive(TypeDefPrimitive::U32) => {
+                Primitive::U32(Compact::<u32>::decode(input)?.0)
+            }
+            TypeDef::Primitive(TypeDefPrimitive::U64) => {
+                Primitive::U64(Compact::<u64>::decode(input)?.0)
+            }
+            TypeDef::Primitive(TypeDefPrimitive::U128) => {
+                Primitive::U128(Compact::<u128>::decode(input)?.0)
+            }
+            TypeDef::Composite(composite) if composite.fields().len() == 1 => {
+                let field = &composite.fields()[0];
+                let value = self.decode_compact(field.ty().id(), input)?;
+                let fields = match field.name() {
+                    Some(name) => Composite::Named(vec![(name.clone(), value)]),
+                    None => Composite::Unnamed(vec![value]),
+                };
+                return Ok(Value::Composite(fields));
+            }
+            _ => {
+                return Err(Error::UnsupportedDynamicType {
+                    id: id.get(),
+                    reason: "only unsigned integers and single field structs can be compact",
+                })
+            }
+        };
+        Ok(Value::Primitive(primitive))
+    }
+}
+
+struct ValueEncoder<'a> {
+    types: &'a PortableRegistry,
+}
+
+impl ValueEncoder<'_> {
+    fn encode(&self, id: NonZeroU32, value: &Value, output: &mut Vec<u8>) -> Result<()> {
+        let (id, ty) = resolve_type(self.types, id)?;
+        let mismatch = |expected| Error::ValueMismatch {
+            id: id.get(),
+            expected,
+        };
+        if let Some((store_bits, msb0)) = bit_sequence_layout(self.types, id, ty)? {
+            return match value {
+                Value::BitSequence(bits) => {
+                    encode_bits(bits, store_bits, msb0, output);
+                    Ok(())
+                }
+                _ => Err(mismatch("a bit sequence")),
+            };
+        }
+
+        match (ty.type_def(), value) {
+            (TypeDef::Composite(composite), Value::Composite(fields)) => {
+                self.encode_fields(id, composite.fields(), fields, output)
+            }
+            (TypeDef::Variant(variant), Value::Variant { name, fields, .. }) => {
+                let (position, v) = variant
+                    .variants()
+                    .iter()
+                    .enumerate()
+                    .find(|(_, v)| v.name() == name)
+                    .ok_or_else(|| Error::UnknownVariant {
+                        id: id.get(),
+                        variant: name.clone(),
+                    })?;
+                variant_index(position, v)?.encode_to(output);
+                self.encode_fields(id, v.fields(), fields, output)
+            }
+            (TypeDef::Sequence(sequence), Value::Sequence(elements)) => {
+                Compact(elements.len() as u32).encode_to(output);
+                for element in elements {
+                    self.encode(sequence.type_param().id(), element, output)?;
+                }
+                Ok(())
+            }
+            (TypeDef::Array(array), Value::Sequence(elements))
+                if elements.len() == array.len() as usize =>
+            {
+                for element in elements {
+                    self.encode(array.type_param().id(), element, output)?;
+                }
+                Ok(())
+            }
+            (TypeDef::Array(_), _) => Err(mismatch("an array of the same length")),
+            (TypeDef::Tuple(tuple), Value::Tuple(elements))
+                if elements.len() == tuple.fields().len() =>
+            {
+                for (field, element) in tuple.fields().iter().zip(elements) {
+                    self.encode(field.id(), element, output)?;
+                }
+                Ok(())
+            }
+            (TypeDef::Tuple(_), _) => Err(mismatch("a tuple of the same length")),
+            (TypeDef::Primitive(primitive), Value::Primitive(value)) => {
+                encode_primitive(primitive, value, output).ok_or_else(|| mismatch("a primitive"))
+            }
+            (TypeDef::Compact(compact), value) => {
+                self.encode_compact(compact.type_param().id(), value, output)
+            }
+            (TypeDef::Phantom(_), Value::Tuple(elements)) if elements.is_empty() => Ok(()),
+            (TypeDef::Composite(_), _) => Err(mismatch("a composite")),
+            (TypeDef::Variant(_), _) => Err(mismatch("a variant")),
+            (TypeDef::Sequence(_), _) => Err(mismatch("a sequence")),
+            (TypeDef::Primitive(_), _) => Err(mismatch("a primitive")),
+            (TypeDef::Phantom(_), _) => Err(mismatch("an empty tuple")),
+        }
+    }
+
+    fn encode_fields(
+        &self,
+        id: NonZeroU32,
+        fields: &[Field<PortableForm>],
+        values: &Composite,
+        output: &mut Vec<u8>,
+    ) -> Result<()> {
+        let values = match values {
+            Composite::Unnamed(values) if values.len() == fields.len() => {
+                values.iter().collect::<Vec<_>>()
+            }
+            Composite::Named(values) => field_names(fields)
+                .and_then(|names| {
+                    names
+                        .into_iter()
+                        .map(|name| values.iter().find(|(n, _)| n == name).map(|(_, v)| v))
+                        .collect::<Option<Vec<_>>>()
+                })
+                .filter(|_| values.len() == fields.len())
+                .ok_or(Error::ValueMismatch {
+                    id: id.get(),
+                    expected: "the named fields of the type",
+                })?,
+            Composite::Unnamed(_) => {
+                return Err(Error::ValueMismatch {
+                    id: id.get(),
+                    expected: "the same number of fields",
+                })
+            }
+        };
+        for (field, value) in fields.iter().zip(values) {
+            self.encode(field.ty().id(), value, output)?;
+        }
+        Ok(())
+    }
+
+    fn encode_compact(&self, id: NonZeroU32, value: &Value, output: &mut Vec<u8>) -> Result<()> {
+        let (id, ty) = resolve_type(self.types, id)?;
+        match (ty.type_def(), value) {
+            (TypeDef::Primitive(primitive), Value::Primitive(value)) => {
+                match (primitive, value) {
+                    (TypeDefPrimitive::U8, Primitive::U8(v)) => Compact(*v).encode_to(output),
+                    (TypeDefPrimitive::U16, Primitive::U16(v)) => Compact(*v).encode_to(output),
+                    (TypeDefPrimitive::U32, Primitive::U32(v)) => Compact(*v).encode_to(output),
+                    (TypeDefPrimitive::U64, Primitive::U64(v)) => Compact(*v).encode_to(output),
+                    (TypeDefPrimitive::U128, Primitive::U128(v)) => Compact(*v).encode_to(output),
+                    _ => {
+                        return Err(Error::ValueMismatch {
+                            id: id.get(),
+                            expected: "an unsigned integer of the same size",
+                        })
+                    }
+                }
+                Ok(())
+            }
+            (TypeDef::Composite(composite), Value::Composite(fields))
+                if composite.fields().len() == 1 =>
+            {
+                let value = match fields {
+                    Composite::Named(fields) if fields.len() == 1 => &fields[0].1,
+                    Composite::Unnamed(fields) if fields.len() == 1 => &fields[0],
+                    _ => {
+                        return Err(Error::ValueMismatch {
+                            id: id.get(),
+                            expected: "a single field",
+                        })
+                    }
+                };
+                self.encode_compact(composite.fields()[0].ty().id(), value, output)
+            }
+            _ => Err(Error::ValueMismatch {
+                id: id.get(),
+                expected: "an unsigned integer or a single field struct",
+            }),
+        }
+    }
+}
+
+/// The names of the fields if they are all named.
+fn field_names(fields: &[Field<PortableForm>]) -> Option<Vec<&String>> {
+    fields.iter().map(|f| f.name()).collect()
+}
+
+fn decode_primitive(primitive: &TypeDefPrimitive, input: &mut &[u8]) -> Result<Primitive> {
+    let value = match primitive {
+        TypeDefPrimitive::Bool => Primitive::Bool(bool::decode(input)?),
+        TypeDefPrimitive::Char => {
+            let c = u32::decode(input)?;
+            Primitive::Char(char::from_u32(c).ok_or(scale::Error::from("Invalid char"))?)
+        }
+        TypeDefPrimitive::Str => Primitive::Str(String::decode(input)?),
+        TypeDefPrimitive::U8 => Primitive::U8(u8::decode(input)?),
+        TypeDefPrimitive::U16 => Primitive::U16(u16::decode(input)?),
+        TypeDefPrimitive::U32 => Primitive::U32(u32::decode(input)?),
+        TypeDefPrimitive::U64 => Primitive::U64(u64::decode(input)?),
+        TypeDefPrimitive::U128 => Primitive::U128(u128::decode(input)?),
+        TypeDefPrimitive::U256 => Primitive::U256(<[u8; 32]>::decode(input)?),
+        TypeDefPrimitive::I8 => Primitive::I8(i8::decode(input)?),
+        TypeDefPrimitive::I16 => Primitive::I16(i16::decode(input)?),
+        TypeDefPrimitive::I32 => Primitive::I32(i32::decode(input)?),
+        TypeDefPrimitive::I64 => Primitive::I64(i64::decode(input)?),
+        TypeDefPrimitive::I128 => Primitive::I128(i128::decode(input)?),
+        TypeDefPrimitive::I256 => Primitive::I256(<[u8; 32]>::decode(input)?),
+    };
+    Ok(value)
+}
+
+/// Encode the primitive value, or return `None` if it is not of the primitive type.
+fn encode_primitive(
+    primitive: &TypeDefPrimitive,
+    value: &Primitive,
+    output: &mut Vec<u8>,
+) -> Option<()> {
+    match (primitive, value) {
+        (TypeDefPrimitive::Bool, Primitive::Bool(v)) => v.encode_to(output),
+        (TypeDefPrimitive::Char, Primitive::Char(v)) => (*v as u32).encode_to(output),
+        (TypeDefPrimitive::Str, Primitive::Str(v)) => v.encode_to(output),
+        (TypeDefPrimitive::U8, Primitive::U8(v)) => v.encode_to(output),
+        (TypeDefPrimitive::U16, Primitive::U16(v)) => v.encode_to(output),
+        (TypeDefPrimitive::U32, Primitive::U32(v)) => v.encode_to(output),
+        (TypeDefPrimitive::U64, Primitive::U64(v)) => v.encode_to(output),
+        (TypeDefPrimitive::U128, Primitive::U128(v)) => v.encode_to(output),
+        (TypeDefPrimitive::U256, Primitive::U256(v)) => v.encode_to(output),
+        (TypeDefPrimitive::I8, Primitive::I8(v)) => v.encode_to(output),
+        (TypeDefPrimitive::I16, Primitive::I16(v)) => v.encode_to(output),
+        (TypeDefPrimitive::I32, Primitive::I32(v)) => v.encode_to(output),
+        (TypeDefPrimitive::I64, Primitive::I64(v)) => v.encode_to(output),
+        (TypeDefPrimitive::I128, Primitive::I128(v)) => v.encode_to(output),
+        (TypeDefPrimitive::I256, Primitive::I256(v)) => v.encode_to(output),
+        _ => return None,
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::read_metadata;
+    use frame_metadata::v13::RuntimeMetadataV13;
+
+    fn decode_metadata(file: &str) -> RuntimeMetadataV13 {
+        crate::decode_metadata_v13(&read_metadata(file)).unwrap()
+    }
+
+    fn u8s(bytes: &[u8]) -> Value {
+        Value::Sequence(
+            bytes
+                .iter()
+                .map(|b| Value::Primitive(Primitive::U8(*b)))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn constants_round_trip() {
+        for file in &["node-runtime.scale", "test-runtime-v14.scale"] {
+            let metadata = decode_metadata(file);
+            for constant in metadata.pallets.iter().flat_map(|p| &p.constants) {
+                let id = constant.ty.id();
+                let mut input = &constant.value[..];
+                let value = Value::decode(&metadata.types, id, &mut input).unwrap();
+
+                assert!(input.is_empty(), "{} was not fully decoded", constant.name);
+                assert_eq!(value.encode(&metadata.types, id).unwrap(), constant.value);
+            }
+        }
+    }
+
+    #[test]
+    fn encode_and_decode_call() {
+        let metadata = decode_metadata("test-runtime-v14.scale");
+        let balances = metadata.pallets.iter().find(|p| p.name == "Balances");
+        let call_ty = balances.unwrap().calls.as_ref().unwrap().ty.id();
+        let call = Value::Variant {
+            name: "transfer_keep_alive".into(),
+            index: 1,
+            fields: Composite::Named(vec![
+                (
+                    "dest".into(),
+                    Value::Variant {
+                        name: "Id".into(),
+                        index: 0,
+                        fields: Composite::Unnamed(vec![Value::Composite(Composite::Unnamed(
+                            vec![u8s(&[7; 32])],
+                        ))]),
+                    },
+                ),
+                ("value".into(), Value::Primitive(Primitive::U128(1_000))),
+            ]),
+        };
+
+        let encoded = call.encode(&metadata.types, call_ty).unwrap();
+        let mut expected = vec![1, 0];
+        expected.extend_from_slice(&[7; 32]);
+        Compact(1_000u128).encode_to(&mut expected);
+        assert_eq!(encoded, expected);
+
+        let decoded = Value::decode(&metadata.types, call_ty, &mut &encoded[..]).unwrap();
+        assert_eq!(decoded, call);
+    }
+
+    #[test]
+    fn value_must_match_type() {
+        let metadata = decode_metadata("test-runtime-v14.scale");
+        let balances = metadata.pallets.iter().find(|p| p.name == "Balances");
+        let call_ty = balances.unwrap().calls.as_ref().unwrap().ty.id();
+        let call = |name: &str, fields| Value::Variant {
+            name: name.into(),
+            index: 0,
+            fields: Composite::Named(fields),
+        };
+        let value = ("value".into(), Value::Primitive(Primitive::U128(1)));
+
+        let unknown = call("transfer_all", vec![value.clone()]).encode(&metadata.types, call_ty);
+        assert!(matches!(unknown, Err(Error::UnknownVariant { .. })));
+        let missing_field = call("transfer", vec![value]).encode(&metadata.types, call_ty);
+        assert!(matches!(missing_field, Err(Error::ValueMismatch { .. })));
+        let unknown_index = Value::decode(&metadata.types, call_ty, &mut &[9][..]);
+        assert!(matches!(unknown_index, Err(Error::UnknownVariant { .. })));
+    }
+
+    #[test]
+    fn bit_sequences() {
+        let bits = [true, false, true, true, false, false, false, false, true];
+        let mut lsb0_u8 = Vec::new();
+        encode_bits(&bits, 8, false, &mut lsb0_u8);
+        assert_eq!(lsb0_u8, [36, 0b0000_1101, 0b0000_0001]);
+        assert_eq!(decode_bits(&mut &lsb0_u8[..], 8, false).unwrap(), bits);
+
+        let mut msb0_u16 = Vec::new();
+        encode_bits(&bits[..3], 16, true, &mut msb0_u16);
+        assert_eq!(msb0_u16, [12, 0x00, 0b1010_0000]);
+        assert_eq!(
+            decode_bits(&mut &msb0_u16[..], 16, true).unwrap(),
+            bits[..3]
+        );
+
+        assert!(decode_bits(&mut &[36, 0][..], 8, false).is_err());
+    }
+}
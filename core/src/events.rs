@@ -0,0 +1,197 @@
+//! Splitting the events of a block into their records with the type registry of the runtime which
+//! emitted them, rather than the one the code was generated from.
+//!
+//! After a runtime upgrade, the generated outer `Event` can fail to decode an event of a new
+//! pallet or variant, and since events are not length prefixed, none of the following records
+//! could be decoded either. The [`EventRecordsDecoder`] skips over each record with the
+//! [`dynamic`](crate::dynamic) decoder instead, so the generated `EventRecords::decode_tolerant`
+//! can decode the known events and keep the bytes of the unknown ones.
+
+use crate::{dynamic::Value, Error, Result};
+use frame_metadata::v13::RuntimeMetadataV13;
+use scale::{Compact, Decode as _};
+use scale_info::{
+    form::PortableForm, prelude::num::NonZeroU32, Field, PortableRegistry, Type, TypeDef,
+};
+
+/// An event record of which each field is kept encoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawEventRecord {
+    /// The encoded phase of the block the event was emitted in.
+    pub phase: Vec<u8>,
+    /// The index of the pallet which emitted the event.
+    pub pallet_index: u8,
+    /// The index of the event in the pallet's `Event` enum.
+    pub variant_index: u8,
+    /// The encoded event, starting with the pallet and variant index.
+    pub event: Vec<u8>,
+    /// The encoded topics of the event.
+    pub topics: Vec<u8>,
+}
+
+/// Splits the value of the `System::Events` storage entry into [`RawEventRecord`]s, using the type
+/// registry of the metadata of a node.
+#[derive(Debug)]
+pub struct EventRecordsDecoder {
+    types: PortableRegistry,
+    record: Vec<(String, NonZeroU32)>,
+}
+
+impl EventRecordsDecoder {
+    /// Create the decoder from the metadata of a node, e.g. the response to a `state_getMetadata`
+    /// request, in any of the formats accepted by
+    /// [`generate_runtime_types_from_bytes`](crate::generate_runtime_types_from_bytes).
+    ///
+    /// # Errors
+    ///
+    /// If the metadata has no `System::Events` storage entry holding a sequence of event records
+    /// with a `phase`, `event` and `topics` field.
+    pub fn new(metadata: &[u8]) -> Result<Self> {
+        let metadata = crate::decode_metadata_v13(metadata)?;
+        let record = event_record_fields(&metadata)
+            .ok_or(Error::MissingEventRecords)?
+            .iter()
+            .map(|field| (field.name().cloned().unwrap_or_default(), field.ty().id()))
+            .collect();
+        Ok(Self {
+            types: metadata.types,
+            record,
+        })
+    }
+
+    /// Split the encoded events of a block into their records.
+    pub fn decode(&self, bytes: &[u8]) -> Result<Vec<RawEventRecord>> {
+        let input = &mut &bytes[..];
+        let len = Compact::<u32>::decode(input)?.0;
+        let records = (0..len)
+            .map(|_| self.decode_record(input))
+            .collect::<Result<_>>()?;
+        if !input.is_empty() {
+            return Err(scale::Error::from("Unexpected bytes after the event records").into());
+        }
+        Ok(records)
+    }
+
+    fn decode_record(&self, input: &mut &[u8]) -> Result<RawEventRecord> {
+        let mut record = RawEventRecord {
+            phase: Vec::new(),
+            pallet_index: 0,
+            variant_index: 0,
+            event: Vec::new(),
+            topics: Vec::new(),
+        };
+        for (name, ty) in &self.record {
+            let start = *input;
+            Value::decode(&self.types, *ty, input)?;
+            let bytes = start[..start.len() - input.len()].to_vec();
+            match name.as_str() {
+                "phase" => record.phase = bytes,
+                "event" => record.event = bytes,
+                "topics" => record.topics = bytes,
+                _ => (),
+            }
+        }
+        match record.event[..] {
+            [pallet_index, variant_index, ..] => {
+                record.pallet_index = pallet_index;
+                record.variant_index = variant_index;
+                Ok(record)
+            }
+            _ => {
+                Err(scale::Error::from("Expected an event with a pallet and variant index").into())
+            }
+        }
+    }
+}
+
+/// Returns the fields of the event records stored in `System::Events`, if it holds a sequence of
+/// records with a `phase`, `event` and `topics` field.
+pub(crate) fn event_record_fields(metadata: &RuntimeMetadataV13) -> Option<&[Field<PortableForm>]> {
+    let types = &metadata.types;
+    let entry = metadata
+        .pallets
+        .iter()
+        .find(|pallet| pallet.name == "System")?
+        .storage
+        .as_ref()?
+        .entries
+        .iter()
+        .find(|entry| entry.name == "Events")?;
+    let (_, _, value) = crate::generate_storage::storage_entry_types(types, entry);
+    let record: &Type<PortableForm> = match types.resolve(value)?.type_def() {
+        TypeDef::Sequence(sequence) => types.resolve(sequence.type_param().id())?,
+        _ => return None,
+    };
+    let fields = match record.type_def() {
+        TypeDef::Composite(composite) => composite.fields(),
+        _ => return None,
+    };
+    let has_field = |name: &str| {
+        fields
+            .iter()
+            .any(|f| f.name().map(|n| n.as_str()) == Some(name))
+    };
+    if has_field("phase") && has_field("event") && has_field("topics") {
+        Some(fields)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::read_metadata;
+
+    /// The `System::Events` value of a block with a transfer, encoded for the test runtime.
+    const EVENTS: &str = "\
+        0c0000000000000040aa9809000000000000000100000005011111111111111111111111111111111111111111111111\
+        11111111111111111122222222222222222222222222222222222222222222222222222222222222220010a5d4e80000\
+        0000000000000000000001050022222222222222222222222222222222222222222222222222222222222222220010a5\
+        d4e80000000000000000000000043333333333333333333333333333333333333333333333333333333333333333";
+
+    fn decoder() -> EventRecordsDecoder {
+        EventRecordsDecoder::new(&read_metadata("test-runtime-v14.scale")).unwrap()
+    }
+
+    #[test]
+    fn split_event_records() {
+        let bytes = hex::decode(EVENTS).unwrap();
+        let records = decoder().decode(&bytes).unwrap();
+
+        let indices = records
+            .iter()
+            .map(|r| (r.pallet_index, r.variant_index, r.topics.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(indices, [(0, 0, 1), (5, 1, 1), (5, 0, 33)]);
+        assert_eq!(records[1].phase, [0, 1, 0, 0, 0]);
+        assert_eq!(records[2].phase, [1]);
+        assert_eq!(records[1].event.len(), 2 + 32 + 32 + 16);
+        assert_eq!(records[2].event[2..34], [0x22; 32]);
+    }
+
+    #[test]
+    fn event_records_with_bit_sequences() {
+        let decoder =
+            EventRecordsDecoder::new(&read_metadata("test-runtime-v14-indices.scale")).unwrap();
+        // `Example::Stored` with 9 bits, in the `ApplyExtrinsic(1)` phase
+        let bytes = hex::decode("0400010000000702240d0100").unwrap();
+
+        let records = decoder.decode(&bytes).unwrap();
+
+        assert_eq!(records.len(), 1);
+        assert_eq!((records[0].pallet_index, records[0].variant_index), (7, 2));
+        assert_eq!(records[0].event, [7, 2, 36, 0x0d, 0x01]);
+        assert_eq!(records[0].topics, [0]);
+    }
+
+    #[test]
+    fn truncated_event_records_are_an_error() {
+        let bytes = hex::decode(EVENTS).unwrap();
+
+        assert!(decoder().decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(decoder()
+            .decode(&[bytes.clone(), vec![0]].concat())
+            .is_err());
+    }
+}
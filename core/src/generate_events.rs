@@ -0,0 +1,189 @@
+use crate::{
+    events::event_record_fields, generate_types::TypePath, Result, TokenStream2, TypeGenerator,
+};
+use frame_metadata::v13::RuntimeMetadataV13;
+use heck::SnakeCase as _;
+use quote::{format_ident, quote};
+use scale_info::prelude::num::NonZeroU32;
+use std::collections::HashMap;
+
+/// Generate the `EventRecords` decoder of the events of a block, as stored in `System::Events`,
+/// and the `PalletEvent` trait to find the events of a pallet in the outer `Event`.
+///
+/// Nothing is generated if the runtime has no `System::Events` storage entry holding a sequence of
+/// `EventRecord { phase, event, topics }`. With `tolerant`, `EventRecords::decode_tolerant` is
+/// generated as well, see [`generate_tolerant_decoding`].
+pub fn generate_event_records(
+    type_gen: &TypeGenerator,
+    metadata: &RuntimeMetadataV13,
+    tolerant: bool,
+) -> Result<TokenStream2> {
+    let fields = match event_record_fields(metadata) {
+        Some(fields) => fields,
+        None => return Ok(quote! {}),
+    };
+    // the event is decoded as the generated outer `Event`, so only the other field types are needed
+    let field_type = |name: &str| {
+        let field = fields
+            .iter()
+            .find(|f| f.name().map(|n| n.as_str()) == Some(name))
+            .expect("the fields of event records are checked by `event_record_fields`");
+        type_gen.resolve_type_path(field.ty().id(), &[])
+    };
+    let phase = field_type("phase")?;
+    let topics = field_type("topics")?;
+    let tolerant_decoding = if tolerant {
+        generate_tolerant_decoding(&phase, &topics)
+    } else {
+        quote! {}
+    };
+
+    // a trait impl per event type, so skip the event types shared by several pallets
+    let mut pallets_by_event = HashMap::<NonZeroU32, usize>::new();
+    for event in metadata.pallets.iter().filter_map(|p| p.event.as_ref()) {
+        *pallets_by_event.entry(event.ty.id()).or_default() += 1;
+    }
+    let pallet_events = metadata.pallets.iter().filter_map(|pallet| {
+        let event = pallet.event.as_ref()?;
+        if pallets_by_event[&event.ty.id()] > 1 {
+            return None;
+        }
+        let variant_name = format_ident!("{}", pallet.name);
+        let mod_name = format_ident!("{}", pallet.name.to_snake_case());
+        Some(quote! {
+            impl PalletEvent for #mod_name::Event {
+                #[allow(unreachable_patterns)]
+                fn from_event(event: &Event) -> Option<&Self> {
+                    match event {
+                        Event::#variant_name(event) => Some(event),
+                        _ => None,
+                    }
+                }
+            }
+        })
+    });
+
+    let derives = type_gen.derives();
+
+    Ok(quote! {
+        /// An event emitted by the runtime, with the phase of the block it was emitted in and its
+        /// topics.
+        #derives
+        pub struct EventRecord {
+            pub phase: #phase,
+            pub event: Event,
+            pub topics: #topics,
+        }
+
+        /// The events of a block, decoded from the value of the `System::Events` storage entry.
+        #derives
+        pub struct EventRecords(pub Vec<EventRecord>);
+
+        impl EventRecords {
+            /// Returns the phase, event and topics of each record, in the order the events were
+            /// emitted.
+            pub fn iter(&self) -> impl Iterator<Item = (&#phase, &Event, &#topics)> {
+                self.0
+                    .iter()
+                    .map(|record| (&record.phase, &record.event, &record.topics))
+            }
+
+            /// Returns the events of a pallet, e.g. `find::<balances::Event>()`, with the phase
+            /// they were emitted in.
+            pub fn find<'a, E: PalletEvent + 'a>(
+                &'a self,
+            ) -> impl Iterator<Item = (&'a #phase, &'a E)> + 'a {
+                self.0.iter().filter_map(|record| {
+                    E::from_event(&record.event).map(|event| (&record.phase, event))
+                })
+            }
+
+            /// Returns the first event of a pallet, e.g. `find_first::<balances::Event>()`.
+            pub fn find_first<E: PalletEvent>(&self) -> Option<&E> {
+                self.find::<E>().next().map(|(_, event)| event)
+            }
+        }
+
+        /// The event type of a pallet, which is wrapped in a variant of the outer [`Event`].
+        pub trait PalletEvent {
+            /// Returns the pallet event if the outer event is one of the pallet's events.
+            fn from_event(event: &Event) -> Option<&Self>;
+        }
+
+        #( #pallet_events )*
+
+        #tolerant_decoding
+    })
+}
+
+/// Generate `EventRecords::decode_tolerant`, which splits the events of a block into their records
+/// with the type registry of the node's metadata, so that an event of a pallet or variant added by
+/// a runtime upgrade is returned as `TolerantEvent::Unknown` instead of failing the whole block.
+fn generate_tolerant_decoding(phase: &TypePath, topics: &TypePath) -> TokenStream2 {
+    quote! {
+        /// An event decoded by [`EventRecords::decode_tolerant`].
+        #[derive(Debug)]
+        pub enum TolerantEvent {
+            Known(Event),
+            /// An event of a pallet or variant unknown to this module, or whose fields don't decode
+            /// as the generated type.
+            Unknown {
+                pallet_index: u8,
+                variant_index: u8,
+                /// The encoded event, starting with the pallet and variant index.
+                bytes: Vec<u8>,
+            },
+        }
+
+        /// An event record decoded by [`EventRecords::decode_tolerant`].
+        #[derive(Debug)]
+        pub struct TolerantEventRecord {
+            pub phase: #phase,
+            pub event: TolerantEvent,
+            pub topics: #topics,
+        }
+
+        impl TolerantEventRecord {
+            /// Decode the fields of an event record split by the
+            /// `chameleon_core::events::EventRecordsDecoder`, keeping the bytes of the event if it
+            /// is unknown.
+            pub fn from_raw(
+                record: ::chameleon_core::events::RawEventRecord,
+            ) -> Result<Self, ::codec::Error> {
+                let mut input = &record.event[..];
+                let event = match <Event as ::codec::Decode>::decode(&mut input) {
+                    Ok(event) if input.is_empty() => TolerantEvent::Known(event),
+                    _ => TolerantEvent::Unknown {
+                        pallet_index: record.pallet_index,
+                        variant_index: record.variant_index,
+                        bytes: record.event,
+                    },
+                };
+                Ok(Self {
+                    phase: ::codec::Decode::decode(&mut &record.phase[..])?,
+                    event,
+                    topics: ::codec::Decode::decode(&mut &record.topics[..])?,
+                })
+            }
+        }
+
+        impl EventRecords {
+            /// Decode the events of a block, with a decoder created from the metadata of the node
+            /// which emitted them.
+            ///
+            /// Unlike decoding [`EventRecords`], an unknown event doesn't fail the whole block: it
+            /// is skipped with the node's type registry and returned as
+            /// [`TolerantEvent::Unknown`], e.g. after a runtime upgrade added an event.
+            pub fn decode_tolerant(
+                decoder: &::chameleon_core::events::EventRecordsDecoder,
+                bytes: &[u8],
+            ) -> Result<Vec<TolerantEventRecord>, ::chameleon_core::Error> {
+                decoder
+                    .decode(bytes)?
+                    .into_iter()
+                    .map(|record| Ok(TolerantEventRecord::from_raw(record)?))
+                    .collect()
+            }
+        }
+    }
+}
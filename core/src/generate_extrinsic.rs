@@ -0,0 +1,225 @@
+use crate::{Error, Result, TokenStream2, TypeGenerator};
+use frame_metadata::v13::{RuntimeMetadataV13, StorageEntryType};
+use heck::SnakeCase as _;
+use proc_macro2::Ident;
+use quote::{format_ident, quote};
+use std::{collections::HashMap, num::NonZeroU32};
+
+/// Generate the `extrinsic` module, which encodes calls into the runtime's `UncheckedExtrinsic`
+/// format with the signed extensions listed in the extrinsic metadata.
+pub fn generate_extrinsic(
+    type_gen: &TypeGenerator,
+    types_mod_ident: &Ident,
+    metadata: &RuntimeMetadataV13,
+    additional_signed_types: &HashMap<String, syn::Type>,
+) -> Result<TokenStream2> {
+    let extrinsic = &metadata.extrinsic;
+    let version = proc_macro2::Literal::u8_unsuffixed(extrinsic.version);
+    let extensions = extrinsic
+        .signed_extensions
+        .iter()
+        .map(|extension| {
+            let name = format_ident!("{}", extension.identifier.to_snake_case());
+            let ty = type_gen.resolve_type_path(extension.ty.id(), &[])?;
+            let identifier = &extension.identifier;
+            let doc = format!(" The `{}` signed extension.", identifier);
+            Ok(quote! {
+                #[doc = #doc]
+                pub #name: #ty
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let hash = block_hash_type(metadata)
+        .map(|id| type_gen.resolve_type_path(id, &[]))
+        .transpose()?
+        .map(|hash| quote! { #hash });
+    let mut additional_signed_fields = Vec::new();
+    for extension in &extrinsic.signed_extensions {
+        let identifier = &extension.identifier;
+        let (doc, ty) = match additional_signed_types.get(identifier) {
+            Some(ty) => {
+                let doc = format!(" The additional signed data of `{}`.", identifier);
+                (doc, quote! { #ty })
+            }
+            None => match additional_signed(identifier, hash.as_ref())? {
+                Some((doc, ty)) => (doc.to_string(), ty),
+                None => continue,
+            },
+        };
+        let name = format_ident!("{}", identifier.to_snake_case());
+        additional_signed_fields.push(quote! {
+            #[doc = #doc]
+            pub #name: #ty
+        });
+    }
+
+    let derives = type_gen.derives();
+
+    Ok(quote! {
+        pub mod extrinsic {
+            use super::#types_mod_ident;
+
+            /// The version of the extrinsic format.
+            pub const VERSION: u8 = #version;
+
+            /// The values of the signed extensions of the runtime, which are included in signed
+            /// extrinsics, in the order of the metadata.
+            #derives
+            pub struct SignedExtra {
+                #( #extensions, )*
+            }
+
+            /// The data of the signed extensions which is signed, but not included in the
+            /// extrinsic.
+            ///
+            /// Signed extensions without a field here, e.g. `CheckNonce`, have no additional signed
+            /// data.
+            #derives
+            pub struct AdditionalSigned {
+                #( #additional_signed_fields, )*
+            }
+
+            /// Returns the payload to be signed by the sender of a signed extrinsic: the encoded
+            /// call, followed by the signed extensions and their additional signed data.
+            ///
+            /// The signature of a payload longer than 256 bytes is the signature of its
+            /// `blake2_256` hash.
+            pub fn signer_payload(
+                call: &super::Call,
+                extra: &SignedExtra,
+                additional_signed: &AdditionalSigned,
+            ) -> Vec<u8> {
+                let mut payload = ::codec::Encode::encode(call);
+                ::codec::Encode::encode_to(extra, &mut payload);
+                ::codec::Encode::encode_to(additional_signed, &mut payload);
+                payload
+            }
+
+            /// An extrinsic in the format of the runtime's `UncheckedExtrinsic`, e.g. with
+            /// `MultiAddress` and `MultiSignature` as the `Address` and `Signature` types.
+            #[derive(Debug)]
+            pub struct UncheckedExtrinsic<Address, Signature> {
+                /// The address of the sender, the signature of the signer payload and the signed
+                /// extensions, if the extrinsic is signed.
+                pub signature: Option<(Address, Signature, SignedExtra)>,
+                pub function: super::Call,
+            }
+
+            impl<Address, Signature> UncheckedExtrinsic<Address, Signature> {
+                /// Create a signed extrinsic, with the signature of the [`signer_payload`].
+                pub fn new_signed(
+                    function: super::Call,
+                    address: Address,
+                    signature: Signature,
+                    extra: SignedExtra,
+                ) -> Self {
+                    Self {
+                        signature: Some((address, signature, extra)),
+                        function,
+                    }
+                }
+
+                /// Create an unsigned extrinsic, e.g. an inherent.
+                pub fn new_unsigned(function: super::Call) -> Self {
+                    Self {
+                        signature: None,
+                        function,
+                    }
+                }
+            }
+
+            impl<Address, Signature> ::codec::Encode for UncheckedExtrinsic<Address, Signature>
+            where
+                Address: ::codec::Encode,
+                Signature: ::codec::Encode,
+            {
+                fn encode(&self) -> Vec<u8> {
+                    let mut bytes = Vec::new();
+                    match self.signature {
+                        Some(ref signature) => {
+                            // the highest bit of the version marks a signed extrinsic
+                            bytes.push(VERSION | 0b1000_0000);
+                            ::codec::Encode::encode_to(signature, &mut bytes);
+                        }
+                        None => bytes.push(VERSION),
+                    }
+                    ::codec::Encode::encode_to(&self.function, &mut bytes);
+                    // the extrinsic is prefixed with its length, like a `Vec<u8>`
+                    ::codec::Encode::encode(&bytes)
+                }
+            }
+
+            impl<Address, Signature> ::codec::Decode for UncheckedExtrinsic<Address, Signature>
+            where
+                Address: ::codec::Decode,
+                Signature: ::codec::Decode,
+            {
+                fn decode<I: ::codec::Input>(input: &mut I) -> Result<Self, ::codec::Error> {
+                    // the length prefix is not needed to decode the extrinsic
+                    let _length: ::codec::Compact<u32> = ::codec::Decode::decode(input)?;
+                    let version: u8 = ::codec::Decode::decode(input)?;
+                    if version & 0b0111_1111 != VERSION {
+                        return Err("Unsupported extrinsic version".into());
+                    }
+                    let signature = if version & 0b1000_0000 != 0 {
+                        Some(::codec::Decode::decode(input)?)
+                    } else {
+                        None
+                    };
+                    Ok(Self {
+                        signature,
+                        function: ::codec::Decode::decode(input)?,
+                    })
+                }
+            }
+        }
+    })
+}
+
+/// The documentation and type of the additional signed data of the known signed extensions of
+/// `frame_system`, which is not described by the metadata, or `None` for the known signed
+/// extensions without additional signed data. The hash type is the type of block hashes, if found.
+fn additional_signed(
+    identifier: &str,
+    hash: Option<&TokenStream2>,
+) -> Result<Option<(&'static str, TokenStream2)>> {
+    let unknown = || Error::UnknownAdditionalSigned {
+        identifier: identifier.to_string(),
+    };
+    Ok(Some(match identifier {
+        "CheckSpecVersion" => (" The `spec_version` of the runtime.", quote! { u32 }),
+        "CheckTxVersion" => (
+            " The `transaction_version` of the runtime.",
+            quote! { u32 },
+        ),
+        "CheckGenesis" => (
+            " The hash of the genesis block.",
+            hash.ok_or_else(unknown)?.clone(),
+        ),
+        "CheckMortality" | "CheckEra" => (
+            " The hash of the first block of the mortality period, or the genesis hash if immortal.",
+            hash.ok_or_else(unknown)?.clone(),
+        ),
+        "CheckNonZeroSender" | "CheckNonce" | "CheckWeight" | "ChargeTransactionPayment"
+        | "ChargeAssetTxPayment" => return Ok(None),
+        _ => return Err(unknown()),
+    }))
+}
+
+/// The id of the type of block hashes, the value of the `System::BlockHash` storage map.
+fn block_hash_type(metadata: &RuntimeMetadataV13) -> Option<NonZeroU32> {
+    let system = metadata
+        .pallets
+        .iter()
+        .find(|pallet| pallet.name == "System")?;
+    let entry = system
+        .storage
+        .as_ref()?
+        .entries
+        .iter()
+        .find(|entry| entry.name == "BlockHash")?;
+    match entry.ty {
+        StorageEntryType::Map { ref value, .. } => Some(value.id()),
+        _ => None,
+    }
+}
@@ -0,0 +1,595 @@
+use crate::{Error, Result, TokenStream2, TypeGeneratorConfig};
+use frame_metadata::{
+    decode_different::DecodeDifferent,
+    v12::{EventMetadata, FunctionMetadata, RuntimeMetadataV12},
+};
+use heck::{CamelCase as _, SnakeCase as _};
+use quote::{format_ident, quote, ToTokens as _};
+use std::collections::HashMap;
+
+/// Maps the type names of legacy (V12) metadata, e.g. `T::Balance` or `BalanceOf<T>`, onto Rust
+/// types.
+///
+/// Type names are looked up with all whitespace removed, first by the full name and then for paths
+/// by the name without generic arguments, so `BalanceOf` matches `BalanceOf<T>`. Qualified names
+/// such as `<T as Trait>::Balance` are also looked up as `T::Balance`.
+///
+/// Primitives, tuples, arrays, `Compact`, `Vec`, `Option`, `Box` and `BTreeMap` don't need to be
+/// registered. Paths in [`TypeGeneratorConfig::type_substitutes`] are mapped onto their substitutes,
+/// and `U256` onto `primitive_types::U256` with [`TypeGeneratorConfig::primitive_types`].
+#[derive(Clone, Debug, Default)]
+pub struct LegacyTypeRegistry {
+    types: HashMap<String, syn::Type>,
+}
+
+impl LegacyTypeRegistry {
+    /// Construct a new, empty [`LegacyTypeRegistry`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a registry with a `<type name> = <rust type>` mapping on each line, e.g.
+    /// `T::AccountId = ::sp_core::crypto::AccountId32`. Empty lines and lines starting with `#`
+    /// are ignored.
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut registry = Self::new();
+        for (i, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, ty) = line
+                .split_once('=')
+                .ok_or(Error::InvalidLegacyTypeRegistry { line: i + 1 })?;
+            let ty =
+                syn::parse_str(ty).map_err(|_| Error::InvalidLegacyTypeRegistry { line: i + 1 })?;
+            registry.insert(name, ty);
+        }
+        Ok(registry)
+    }
+
+    /// Map the legacy type name onto the given Rust type.
+    pub fn insert(&mut self, name: &str, ty: syn::Type) {
+        self.types.insert(normalize(name), ty);
+    }
+
+    /// Returns the Rust type of a field with the given legacy type name, along with the
+    /// `#[codec(compact)]` attribute for `Compact<T>` types.
+    fn resolve_field(
+        &self,
+        name: &str,
+        config: &TypeGeneratorConfig,
+    ) -> Result<(Option<TokenStream2>, syn::Type)> {
+        let ty = parse_type_name(name)?;
+        if let Some(inner) = compact_type_param(&ty) {
+            Ok((
+                Some(quote! { #[codec(compact)] }),
+                self.resolve(inner, name, config)?,
+            ))
+        } else {
+            Ok((None, self.resolve(&ty, name, config)?))
+        }
+    }
+
+    fn resolve(
+        &self,
+        ty: &syn::Type,
+        name: &str,
+        config: &TypeGeneratorConfig,
+    ) -> Result<syn::Type> {
+        let key = normalize(&ty.to_token_stream().to_string());
+        if let Some(ty) = self.types.get(&key) {
+            return Ok(ty.clone());
+        }
+
+        let unknown = || Error::UnknownLegacyType {
+            name: name.to_string(),
+        };
+        match ty {
+            syn::Type::Path(type_path) => {
+                if let Some(ref qself) = type_path.qself {
+                    // `<T as Trait>::Balance` is looked up as `T::Balance`
+                    let segments = type_path.path.segments.iter().skip(qself.position);
+                    let key = std::iter::once(qself.ty.to_token_stream().to_string())
+                        .chain(segments.map(|segment| segment.ident.to_string()))
+                        .collect::<Vec<_>>()
+                        .join("::");
+                    return self
+                        .types
+                        .get(&normalize(&key))
+                        .cloned()
+                        .ok_or_else(unknown);
+                }
+                let key = type_path
+                    .path
+                    .segments
+                    .iter()
+                    .map(|segment| segment.ident.to_string())
+                    .collect::<Vec<_>>()
+                    .join("::");
+                if let Some(ty) = self.types.get(&key) {
+                    return Ok(ty.clone());
+                }
+
+                let segment = type_path.path.segments.last().ok_or_else(unknown)?;
+                let params = match segment.arguments {
+                    syn::PathArguments::AngleBracketed(ref args) => args
+                        .args
+                        .iter()
+                        .map(|arg| match arg {
+                            syn::GenericArgument::Type(ty) => self.resolve(ty, name, config),
+                            _ => Err(unknown()),
+                        })
+                        .collect::<Result<Vec<_>>>()?,
+                    syn::PathArguments::None => Vec::new(),
+                    syn::PathArguments::Parenthesized(_) => return Err(unknown()),
+                };
+                if let Some(substitute) = config.type_substitutes.get(&key) {
+                    let ty = if params.is_empty() {
+                        quote! { #substitute }
+                    } else {
+                        quote! { #substitute<#( #params ),*> }
+                    };
+                    return Ok(syn::parse_quote! { #ty });
+                }
+                let ident = segment.ident.to_string();
+                let ty = match (ident.as_str(), &params[..]) {
+                    (
+                        "bool" | "char" | "u8" | "u16" | "u32" | "u64" | "u128" | "i8" | "i16"
+                        | "i32" | "i64" | "i128",
+                        [],
+                    ) => return Ok(ty.clone()),
+                    ("U256", []) if config.primitive_types => quote! { ::primitive_types::U256 },
+                    ("Compact", [param]) => quote! { ::codec::Compact<#param> },
+                    ("Vec", [param]) => quote! { Vec<#param> },
+                    ("Option", [param]) => quote! { Option<#param> },
+                    ("Box", [param]) => quote! { ::std::boxed::Box<#param> },
+                    ("BTreeMap", [key, value]) => {
+                        quote! { ::std::collections::BTreeMap<#key, #value> }
+                    }
+                    _ => return Err(unknown()),
+                };
+                Ok(syn::parse_quote! { #ty })
+            }
+            syn::Type::Tuple(tuple) => {
+                let elems = tuple
+                    .elems
+                    .iter()
+                    .map(|elem| self.resolve(elem, name, config))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(syn::parse_quote! { ( #( #elems, )* ) })
+            }
+            syn::Type::Array(array) => {
+                let elem = self.resolve(&array.elem, name, config)?;
+                let len = &array.len;
+                Ok(syn::parse_quote! { [#elem; #len] })
+            }
+            syn::Type::Paren(paren) => self.resolve(&paren.elem, name, config),
+            _ => Err(unknown()),
+        }
+    }
+}
+
+/// Generates the runtime module from legacy metadata, with the same layout of the calls and events
+/// of each pallet as the [`crate::generate_runtime::RuntimeGenerator`].
+///
+/// The generated types have no metadata paths, so [`TypeGeneratorConfig::type_derives`] are looked
+/// up by their path in the runtime module, e.g. `Event`, `balances::Call` or
+/// `balances::calls::Transfer`.
+pub struct LegacyRuntimeGenerator {
+    metadata: RuntimeMetadataV12,
+}
+
+impl LegacyRuntimeGenerator {
+    pub fn new(metadata: RuntimeMetadataV12) -> Self {
+        Self { metadata }
+    }
+
+    pub fn generate_runtime(
+        &self,
+        mod_name: &str,
+        config: &TypeGeneratorConfig,
+    ) -> Result<TokenStream2> {
+        let mut modules = Vec::new();
+        let mut outer_call_variants = Vec::new();
+        let mut outer_event_variants = Vec::new();
+
+        for module in decoded(&self.metadata.modules) {
+            let name = decoded(&module.name);
+            let pallet = name.to_snake_case();
+            let mod_name = format_ident!("{}", pallet);
+            let variant_name = format_ident!("{}", name);
+            let index = proc_macro2::Literal::u8_unsuffixed(module.index);
+
+            let calls = module
+                .calls
+                .as_ref()
+                .map(decoded)
+                .map_or(&[][..], |c| &c[..]);
+            let calls = if !calls.is_empty() {
+                outer_call_variants.push(quote! {
+                    #[codec(index = #index)]
+                    #variant_name(#mod_name::Call),
+                });
+                generate_calls(config, &pallet, calls)?
+            } else {
+                quote! {}
+            };
+
+            let events = module
+                .event
+                .as_ref()
+                .map(decoded)
+                .map_or(&[][..], |e| &e[..]);
+            let event = if !events.is_empty() {
+                outer_event_variants.push(quote! {
+                    #[codec(index = #index)]
+                    #variant_name(#mod_name::Event),
+                });
+                generate_event(config, &pallet, events)?
+            } else {
+                quote! {}
+            };
+
+            modules.push(quote! {
+                pub mod #mod_name {
+                    #calls
+                    #event
+                }
+            });
+        }
+
+        let mod_name = format_ident!("{}", mod_name);
+        let call_derives = config.derives_for_type("Call");
+        let event_derives = config.derives_for_type("Event");
+        Ok(quote! {
+            #[allow(dead_code, unused_imports, non_camel_case_types)]
+            pub mod #mod_name {
+                #call_derives
+                pub enum Call {
+                    #( #outer_call_variants )*
+                }
+
+                #event_derives
+                pub enum Event {
+                    #( #outer_event_variants )*
+                }
+
+                #( #modules )*
+            }
+        })
+    }
+}
+
+fn generate_calls(
+    config: &TypeGeneratorConfig,
+    pallet: &str,
+    calls: &[FunctionMetadata],
+) -> Result<TokenStream2> {
+    let mut call_structs = Vec::new();
+    let mut call_variants = Vec::new();
+
+    for (index, call) in calls.iter().enumerate() {
+        let call_name = decoded(&call.name);
+        let name = format_ident!("{}", call_name.to_camel_case());
+        let fn_name = format_ident!("{}", call_name.to_snake_case());
+        let docs = decoded(&call.documentation);
+        let index = proc_macro2::Literal::u8_unsuffixed(index as u8);
+
+        let mut arg_names = Vec::new();
+        let mut fields = Vec::new();
+        let mut params = Vec::new();
+        for arg in decoded(&call.arguments) {
+            let arg_name = format_ident!("{}", decoded(&arg.name));
+            let (attrs, ty) = config
+                .legacy_types
+                .resolve_field(decoded(&arg.ty).as_str(), config)?;
+            fields.push(quote! { #attrs pub #arg_name: #ty });
+            params.push(quote! { #arg_name: #ty });
+            arg_names.push(arg_name);
+        }
+
+        let derives = config.derives_for_type(&format!("{}::calls::{}", pallet, name));
+        call_structs.push(quote! {
+            #( #[doc = #docs] )*
+            #derives
+            pub struct #name {
+                #( #fields ),*
+            }
+
+            #( #[doc = #docs] )*
+            pub fn #fn_name( #( #params ),* ) -> super::Call {
+                super::Call::#name(#name { #( #arg_names ),* })
+            }
+        });
+        call_variants.push(quote! {
+            #[codec(index = #index)]
+            #name(calls::#name),
+        });
+    }
+
+    let derives = config.derives_for_type(&format!("{}::Call", pallet));
+    Ok(quote! {
+        pub mod calls {
+            #( #call_structs )*
+        }
+
+        #derives
+        pub enum Call {
+            #( #call_variants )*
+        }
+    })
+}
+
+fn generate_event(
+    config: &TypeGeneratorConfig,
+    pallet: &str,
+    events: &[EventMetadata],
+) -> Result<TokenStream2> {
+    let variants = events
+        .iter()
+        .enumerate()
+        .map(|(index, event)| {
+            let name = format_ident!("{}", decoded(&event.name));
+            let docs = decoded(&event.documentation);
+            let index = proc_macro2::Literal::u8_unsuffixed(index as u8);
+            let fields = decoded(&event.arguments)
+                .iter()
+                .map(|arg| {
+                    let (attrs, ty) = config.legacy_types.resolve_field(arg, config)?;
+                    Ok(quote! { #attrs #ty })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let fields = if fields.is_empty() {
+                quote! {}
+            } else {
+                quote! { ( #( #fields ),* ) }
+            };
+            Ok(quote! {
+                #( #[doc = #docs] )*
+                #[codec(index = #index)]
+                #name #fields,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let derives = config.derives_for_type(&format!("{}::Event", pallet));
+    Ok(quote! {
+        #derives
+        pub enum Event {
+            #( #variants )*
+        }
+    })
+}
+
+/// Returns the value of metadata decoded from its SCALE encoding.
+fn decoded<B, O>(value: &DecodeDifferent<B, O>) -> &O {
+    match value {
+        DecodeDifferent::Decoded(value) => value,
+        DecodeDifferent::Encode(_) => unreachable!("legacy metadata is decoded from bytes"),
+    }
+}
+
+/// Removes all whitespace from a type name.
+fn normalize(name: &str) -> String {
+    name.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+fn parse_type_name(name: &str) -> Result<syn::Type> {
+    syn::parse_str(name).map_err(|_| Error::InvalidLegacyTypeName {
+        name: name.to_string(),
+    })
+}
+
+/// Returns the type parameter of a top level `Compact<T>` type.
+fn compact_type_param(ty: &syn::Type) -> Option<&syn::Type> {
+    let segment = match ty {
+        syn::Type::Path(type_path) if type_path.qself.is_none() => {
+            type_path.path.segments.last()?
+        }
+        _ => return None,
+    };
+    match segment.arguments {
+        syn::PathArguments::AngleBracketed(ref args) if segment.ident == "Compact" => {
+            match args.args.first() {
+                Some(syn::GenericArgument::Type(ty)) if args.args.len() == 1 => Some(ty),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use frame_metadata::v12::{ExtrinsicMetadata, FunctionArgumentMetadata, ModuleMetadata};
+
+    fn registry() -> LegacyTypeRegistry {
+        LegacyTypeRegistry::parse(
+            "
+            # balances
+            T::Balance = u128
+            T::BlockNumber = u32
+            BalanceOf = u128
+            T::AccountId = [u8; 32]
+            <T::Lookup as StaticLookup>::Source = [u8; 32]
+            ",
+        )
+        .unwrap()
+    }
+
+    fn resolve(name: &str) -> String {
+        let (attrs, ty) = registry()
+            .resolve_field(name, &TypeGeneratorConfig::default())
+            .unwrap();
+        quote! { #attrs #ty }.to_string()
+    }
+
+    #[test]
+    fn resolve_type_names() {
+        assert_eq!(resolve("u32"), quote! { u32 }.to_string());
+        assert_eq!(resolve("T::Balance"), quote! { u128 }.to_string());
+        assert_eq!(
+            resolve("<T as Trait>::Balance"),
+            quote! { u128 }.to_string()
+        );
+        assert_eq!(resolve("BalanceOf<T, I>"), quote! { u128 }.to_string());
+        assert_eq!(
+            resolve("Compact<BalanceOf<T>>"),
+            quote! { #[codec(compact)] u128 }.to_string()
+        );
+        assert_eq!(
+            resolve("Vec<T::AccountId>"),
+            quote! { Vec<[u8; 32]> }.to_string()
+        );
+        assert_eq!(
+            resolve("Vec<(T::AccountId, Compact<T::Balance>)>"),
+            quote! { Vec<([u8; 32], ::codec::Compact<u128>,)> }.to_string()
+        );
+        assert_eq!(
+            resolve("<T::Lookup as StaticLookup>::Source"),
+            quote! { [u8; 32] }.to_string()
+        );
+    }
+
+    #[test]
+    fn resolve_substitutes_and_primitive_types() {
+        let config = TypeGeneratorConfig {
+            primitive_types: true,
+            ..Default::default()
+        }
+        .substitute_type("Perbill", syn::parse_quote!(::sp_arithmetic::Perbill))
+        .substitute_type("Timepoint", syn::parse_quote!(::pallet_multisig::Timepoint));
+        let resolve = |name| {
+            let (_, ty) = registry().resolve_field(name, &config).unwrap();
+            ty.to_token_stream().to_string()
+        };
+
+        assert_eq!(
+            resolve("Perbill"),
+            quote! { ::sp_arithmetic::Perbill }.to_string()
+        );
+        assert_eq!(
+            resolve("Option<Timepoint<T::BlockNumber>>"),
+            quote! { Option<::pallet_multisig::Timepoint<u32> > }.to_string()
+        );
+        assert_eq!(
+            resolve("U256"),
+            quote! { ::primitive_types::U256 }.to_string()
+        );
+    }
+
+    #[test]
+    fn unknown_type_name_is_an_error() {
+        assert!(matches!(
+            registry().resolve_field("Vec<T::Hash>", &TypeGeneratorConfig::default()),
+            Err(Error::UnknownLegacyType { name }) if name == "Vec<T::Hash>"
+        ));
+    }
+
+    #[test]
+    fn generate_calls_and_events() {
+        let transfer = FunctionMetadata {
+            name: DecodeDifferent::Decoded("transfer".into()),
+            arguments: DecodeDifferent::Decoded(vec![
+                FunctionArgumentMetadata {
+                    name: DecodeDifferent::Decoded("dest".into()),
+                    ty: DecodeDifferent::Decoded("<T::Lookup as StaticLookup>::Source".into()),
+                },
+                FunctionArgumentMetadata {
+                    name: DecodeDifferent::Decoded("value".into()),
+                    ty: DecodeDifferent::Decoded("Compact<T::Balance>".into()),
+                },
+            ]),
+            documentation: DecodeDifferent::Decoded(vec![" Transfer some balance.".into()]),
+        };
+        let transferred = EventMetadata {
+            name: DecodeDifferent::Decoded("Transfer".into()),
+            arguments: DecodeDifferent::Decoded(vec![
+                "AccountId".into(),
+                "AccountId".into(),
+                "Balance".into(),
+            ]),
+            documentation: DecodeDifferent::Decoded(vec![]),
+        };
+        let balances = ModuleMetadata {
+            name: DecodeDifferent::Decoded("Balances".into()),
+            storage: None,
+            calls: Some(DecodeDifferent::Decoded(vec![transfer])),
+            event: Some(DecodeDifferent::Decoded(vec![transferred])),
+            constants: DecodeDifferent::Decoded(vec![]),
+            errors: DecodeDifferent::Decoded(vec![]),
+            index: 5,
+        };
+        let metadata = RuntimeMetadataV12 {
+            modules: DecodeDifferent::Decoded(vec![balances]),
+            extrinsic: ExtrinsicMetadata {
+                version: 4,
+                signed_extensions: vec![],
+            },
+        };
+
+        let mut config = TypeGeneratorConfig {
+            legacy_types: registry(),
+            ..Default::default()
+        };
+        config
+            .legacy_types
+            .insert("AccountId", syn::parse_quote!([u8; 32]));
+        config
+            .legacy_types
+            .insert("Balance", syn::parse_quote!(u128));
+        let generator = LegacyRuntimeGenerator::new(metadata);
+        let runtime = generator.generate_runtime("runtime", &config).unwrap();
+
+        assert_eq!(
+            runtime.to_string(),
+            quote! {
+                #[allow(dead_code, unused_imports, non_camel_case_types)]
+                pub mod runtime {
+                    #[derive(Debug, ::codec::Encode, ::codec::Decode)]
+                    pub enum Call {
+                        #[codec(index = 5)]
+                        Balances(balances::Call),
+                    }
+
+                    #[derive(Debug, ::codec::Encode, ::codec::Decode)]
+                    pub enum Event {
+                        #[codec(index = 5)]
+                        Balances(balances::Event),
+                    }
+
+                    pub mod balances {
+                        pub mod calls {
+                            #[doc = " Transfer some balance."]
+                            #[derive(Debug, ::codec::Encode, ::codec::Decode)]
+                            pub struct Transfer {
+                                pub dest: [u8; 32],
+                                #[codec(compact)] pub value: u128
+                            }
+
+                            #[doc = " Transfer some balance."]
+                            pub fn transfer(dest: [u8; 32], value: u128) -> super::Call {
+                                super::Call::Transfer(Transfer { dest, value })
+                            }
+                        }
+
+                        #[derive(Debug, ::codec::Encode, ::codec::Decode)]
+                        pub enum Call {
+                            #[codec(index = 0)]
+                            Transfer(calls::Transfer),
+                        }
+
+                        #[derive(Debug, ::codec::Encode, ::codec::Decode)]
+                        pub enum Event {
+                            #[codec(index = 0)]
+                            Transfer([u8; 32], [u8; 32], u128),
+                        }
+                    }
+                }
+            }
+            .to_string()
+        );
+    }
+}
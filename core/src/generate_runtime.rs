@@ -1,78 +1,186 @@
-use crate::{TokenStream2, TypeGenerator};
-use frame_metadata::{v13::RuntimeMetadataV13, RuntimeMetadata, RuntimeMetadataPrefixed};
+use crate::{
+    compat,
+    generate_events::generate_event_records,
+    generate_extrinsic::generate_extrinsic,
+    generate_runtime_api::generate_runtime_api,
+    generate_storage::{generate_storage, generate_storage_types},
+    generate_types::{call_index, variant_index},
+    Error, Result, TokenStream2, TypeGenerator, TypeGeneratorConfig,
+};
+use frame_metadata::{
+    v13::{PalletConstantMetadata, PalletErrorMetadata, RuntimeMetadataV13},
+    RuntimeMetadata, RuntimeMetadataPrefixed,
+};
 use heck::SnakeCase as _;
+use proc_macro2::{Ident, Span};
 use quote::{format_ident, quote};
-use scale_info::prelude::string::ToString;
+use scale::Encode as _;
+use scale_info::{form::PortableForm, prelude::string::ToString, Field, PortableRegistry, TypeDef};
 
 pub struct RuntimeGenerator {
     metadata: RuntimeMetadataV13,
+    /// The offset of the ids of the type registry from the ids of the original metadata, 1 for
+    /// V14 metadata converted into the V13 representation.
+    type_id_offset: u32,
 }
 
 impl RuntimeGenerator {
-    pub fn new(metadata: RuntimeMetadataPrefixed) -> Self {
+    pub fn new(metadata: RuntimeMetadataPrefixed, type_id_offset: u32) -> Result<Self> {
         match metadata.1 {
-            RuntimeMetadata::V13(v13) => Self { metadata: v13 },
-            _ => panic!("Unsupported metadata version {:?}", metadata.1),
+            RuntimeMetadata::V13(v13) => Ok(Self {
+                metadata: v13,
+                type_id_offset,
+            }),
+            other => {
+                // the index of the encoded enum variant is the metadata version
+                let version = other.using_encoded(|bytes| bytes[0]);
+                Err(Error::UnsupportedMetadataVersion(version))
+            }
         }
     }
 
-    pub fn generate_runtime(&self, mod_name: &str) -> TokenStream2 {
-        let type_gen = TypeGenerator::new(&self.metadata.types, "__runtime_types");
-        let types_mod = type_gen.generate_types_mod();
+    pub fn generate_runtime(
+        &self,
+        mod_name: &str,
+        config: TypeGeneratorConfig,
+    ) -> Result<TokenStream2> {
+        let compatibility_check = if config.compatibility_check {
+            generate_compatibility_check(&self.metadata)?
+        } else {
+            quote! {}
+        };
+        let tolerant_events = config.tolerant_events;
+        let runtime_apis = config.runtime_apis.clone();
+        let additional_signed_types = config.additional_signed_types.clone();
+        let type_gen = TypeGenerator::with_config(&self.metadata.types, "__runtime_types", config);
+        let types_mod = type_gen.generate_types_mod()?;
         let types_mod_ident = types_mod.ident();
-        let modules = self.metadata.pallets.iter().map(|pallet| {
-            let mod_name = format_ident!("{}", pallet.name.to_string().to_snake_case());
-            let calls = pallet
-                .calls
-                .as_ref()
-                .map_or(&Vec::new(), |call_metadata| &call_metadata.calls)
-                .iter()
-                .map(|call| {
-                    use heck::CamelCase as _;
-                    // todo: add free functions to Call mod and doc strings
-                    let name = format_ident!("{}", call.name.to_string().to_camel_case());
-                    let args = call.arguments.iter().map(|arg| {
-                        let name = format_ident!("{}", arg.name);
-                        let ty = type_gen.resolve_type_path(arg.ty.id(), &[]);
-                        // todo: add docs and #[compact] attr
-                        quote! { #name: #ty }
-                    });
+        let derives = type_gen.derives();
+        let errors = self
+            .metadata
+            .pallets
+            .iter()
+            .map(|pallet| match pallet.error {
+                Some(ref error) => generate_error(&type_gen, &self.metadata.types, error),
+                None => Ok(None),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let modules = self
+            .metadata
+            .pallets
+            .iter()
+            .zip(&errors)
+            .map(|(pallet, error)| {
+                let mod_name = format_ident!("{}", pallet.name.to_string().to_snake_case());
+                let calls = pallet
+                    .calls
+                    .as_ref()
+                    .map_or(&Vec::new(), |call_metadata| &call_metadata.calls)
+                    .iter()
+                    .map(|call| {
+                        use heck::CamelCase as _;
+                        let name = format_ident!("{}", call.name.to_string().to_camel_case());
+                        let fn_name = format_ident!("{}", call.name.to_snake_case());
+                        let docs = &call.documentation;
+                        let arg_names = call
+                            .arguments
+                            .iter()
+                            .map(|arg| format_ident!("{}", arg.name))
+                            .collect::<Vec<_>>();
+                        let arg_types = call
+                            .arguments
+                            .iter()
+                            .map(|arg| type_gen.resolve_type_path(arg.ty.id(), &[]))
+                            .collect::<Result<Vec<_>>>()?;
+                        let fields = arg_names.iter().zip(&arg_types).map(|(name, ty)| {
+                            let (attrs, ty) = ty.field_type();
+                            quote! { #attrs pub #name: #ty }
+                        });
+                        let params = arg_names.iter().zip(&arg_types).map(|(name, ty)| {
+                            let (_, ty) = ty.field_type();
+                            quote! { #name: #ty }
+                        });
+                        Ok(quote! {
+                            #( #[doc = #docs] )*
+                            #derives
+                            pub struct #name {
+                                #( #fields ),*
+                            }
+
+                            #( #[doc = #docs] )*
+                            pub fn #fn_name( #( #params ),* ) -> super::Call {
+                                super::Call::#name(#name { #( #arg_names ),* })
+                            }
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let event = if let Some(ref event) = pallet.event {
+                    let event_type = type_gen.resolve_type_path(event.ty.id(), &[])?;
                     quote! {
-                        #[derive(Debug, ::codec::Encode, ::codec::Decode)]
-                        pub struct #name {
-                            #( #args ),*
+                        pub type Event = #event_type;
+                    }
+                } else {
+                    quote! {}
+                };
+
+                let storage = pallet
+                    .storage
+                    .as_ref()
+                    .map(|storage| {
+                        generate_storage(&type_gen, &self.metadata.types, types_mod_ident, storage)
+                    })
+                    .transpose()?;
+
+                let constants = generate_constants(&type_gen, types_mod_ident, &pallet.constants)?;
+                let calls = if !calls.is_empty() {
+                    let call_variants = pallet
+                        .calls
+                        .iter()
+                        .flat_map(|call_metadata| {
+                            call_metadata
+                                .calls
+                                .iter()
+                                .map(move |call| (call_metadata, call))
+                        })
+                        .map(|(call_metadata, call)| {
+                            use heck::CamelCase as _;
+                            let name = format_ident!("{}", call.name.to_string().to_camel_case());
+                            let index =
+                                call_index(&self.metadata.types, call_metadata, &call.name)?;
+                            let index = proc_macro2::Literal::u8_unsuffixed(index);
+                            Ok(quote! {
+                                #[codec(index = #index)]
+                                #name(calls::#name),
+                            })
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    quote! {
+                        pub mod calls {
+                            use super::#types_mod_ident;
+                            #( #calls )*
+                        }
+
+                        #derives
+                        pub enum Call {
+                            #( #call_variants )*
                         }
                     }
-                })
-                .collect::<Vec<_>>();
-            let event = if let Some(ref event) = pallet.event {
-                let event_type = type_gen.resolve_type_path(event.ty.id(), &[]);
-                quote! {
-                    pub type Event = #event_type;
-                }
-            } else {
-                quote! {}
-            };
+                } else {
+                    quote! {}
+                };
 
-            let calls = if !calls.is_empty() {
-                quote! {
-                    mod calls {
+                Ok(quote! {
+                    pub mod #mod_name {
                         use super::#types_mod_ident;
-                        #( #calls )*
+                        #calls
+                        #event
+                        #error
+                        #storage
+                        #constants
                     }
-                }
-            } else {
-                quote! {}
-            };
-
-            quote! {
-                pub mod #mod_name {
-                    use super::#types_mod_ident;
-                    #calls
-                    #event
-                }
-            }
-        });
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
 
         let outer_event_variants = self.metadata.pallets.iter().filter_map(|p| {
             let variant_name = format_ident!("{}", p.name);
@@ -88,20 +196,358 @@ impl RuntimeGenerator {
         });
 
         let outer_event = quote! {
-            #[derive(Debug, ::codec::Encode, ::codec::Decode)]
+            #derives
             pub enum Event {
                 #( #outer_event_variants )*
             }
         };
 
+        let outer_call_variants = self.metadata.pallets.iter().filter_map(|p| {
+            let variant_name = format_ident!("{}", p.name);
+            let mod_name = format_ident!("{}", p.name.to_string().to_snake_case());
+            let index = proc_macro2::Literal::u8_unsuffixed(p.index);
+
+            p.calls
+                .as_ref()
+                .filter(|call_metadata| !call_metadata.calls.is_empty())
+                .map(|_| {
+                    quote! {
+                        #[codec(index = #index)]
+                        #variant_name(#mod_name::Call),
+                    }
+                })
+        });
+
+        let outer_call = quote! {
+            #derives
+            pub enum Call {
+                #( #outer_call_variants )*
+            }
+        };
+
+        // pallets without errors have no `Error` enum, so they are skipped
+        let error_pallets = self
+            .metadata
+            .pallets
+            .iter()
+            .zip(&errors)
+            .filter(|(_, error)| error.is_some())
+            .map(|(p, _)| p)
+            .collect::<Vec<_>>();
+        let outer_error_variants = error_pallets.iter().map(|p| {
+            let variant_name = format_ident!("{}", p.name);
+            let mod_name = format_ident!("{}", p.name.to_string().to_snake_case());
+            let index = proc_macro2::Literal::u8_unsuffixed(p.index);
+
+            quote! {
+                #[codec(index = #index)]
+                #variant_name(#mod_name::Error),
+            }
+        });
+        let error_details = error_pallets.iter().map(|p| {
+            let variant_name = format_ident!("{}", p.name);
+            let pallet_name = &p.name;
+
+            quote! {
+                Self::#variant_name(error) => ErrorDetails {
+                    pallet: #pallet_name,
+                    error: error.name(),
+                    docs: error.docs(),
+                },
+            }
+        });
+
+        let outer_error = if error_pallets.is_empty() {
+            quote! {}
+        } else {
+            quote! {
+                /// An error returned from a pallet, as `DispatchError::Module { index, error }`.
+                #derives
+                pub enum RuntimeError {
+                    #( #outer_error_variants )*
+                }
+
+                impl RuntimeError {
+                    /// Decode the error from the `index` of the pallet and the encoded `error`
+                    /// within it, i.e. the index of the error variant followed by its fields.
+                    pub fn from_module_error(
+                        index: u8,
+                        error: &[u8],
+                    ) -> Result<Self, ::codec::Error> {
+                        let bytes = [&[index][..], error].concat();
+                        ::codec::Decode::decode(&mut &bytes[..])
+                    }
+
+                    /// Returns the names of the pallet and the error, and the documentation of the
+                    /// error.
+                    pub fn details(&self) -> ErrorDetails {
+                        match self {
+                            #( #error_details )*
+                        }
+                    }
+                }
+
+                /// Details of a [`RuntimeError`].
+                #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+                pub struct ErrorDetails {
+                    pub pallet: &'static str,
+                    pub error: &'static str,
+                    pub docs: &'static str,
+                }
+            }
+        };
+
+        let event_records = generate_event_records(&type_gen, &self.metadata, tolerant_events)?;
+        let storage_types = generate_storage_types();
+        let extrinsic = generate_extrinsic(
+            &type_gen,
+            types_mod_ident,
+            &self.metadata,
+            &additional_signed_types,
+        )?;
+        let runtime_api = generate_runtime_api(
+            &type_gen,
+            types_mod_ident,
+            &self.metadata.types,
+            &runtime_apis,
+            self.type_id_offset,
+        )?;
+
         let mod_name = format_ident!("{}", mod_name);
-        quote! {
+        Ok(quote! {
             #[allow(dead_code, unused_imports, non_camel_case_types)]
             pub mod #mod_name {
+                #outer_call
                 #outer_event
+                #event_records
+                #outer_error
+                #storage_types
+                #extrinsic
+                #runtime_api
+                #compatibility_check
                 #( #modules )*
                 #types_mod
             }
+        })
+    }
+}
+
+/// Generate the `constants` module of a pallet, with a function returning the decoded value of
+/// each constant, or an error if the value doesn't decode into the generated type.
+fn generate_constants(
+    type_gen: &TypeGenerator,
+    types_mod_ident: &Ident,
+    constants: &[PalletConstantMetadata<PortableForm>],
+) -> Result<TokenStream2> {
+    if constants.is_empty() {
+        return Ok(quote! {});
+    }
+    let constants = constants
+        .iter()
+        .map(|constant| {
+            let fn_name = format_ident!("{}", constant.name.to_snake_case());
+            let ty = type_gen.resolve_type_path(constant.ty.id(), &[])?;
+            let value = &constant.value;
+            let docs = &constant.documentation;
+            Ok(quote! {
+                #( #[doc = #docs] )*
+                pub fn #fn_name() -> Result<#ty, ::codec::Error> {
+                    ::codec::Decode::decode(&mut &[ #( #value ),* ][..])
+                }
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(quote! {
+        pub mod constants {
+            use super::#types_mod_ident;
+            #( #constants )*
         }
+    })
+}
+
+/// Generate the structural hashes of the pallets and their items, and the `check_compatibility`
+/// and `changed_pallets` functions comparing them with the hashes of the metadata of a node.
+fn generate_compatibility_check(metadata: &RuntimeMetadataV13) -> Result<TokenStream2> {
+    let hash_literal =
+        |hash: compat::Hash| syn::LitInt::new(&format!("0x{:032x}", hash), Span::call_site());
+    let item_hashes = |items: &[(String, compat::Hash)]| {
+        let items = items.iter().map(|(name, hash)| {
+            let hash = hash_literal(*hash);
+            quote! { (#name, #hash) }
+        });
+        quote! { &[ #( #items ),* ] }
+    };
+    let pallets = compat::metadata_hashes(metadata)?
+        .iter()
+        .map(|pallet| {
+            let name = &pallet.name;
+            let index = pallet.index;
+            let hash = hash_literal(pallet.hash);
+            let calls = item_hashes(&pallet.calls);
+            let events = item_hashes(&pallet.events);
+            let errors = item_hashes(&pallet.errors);
+            let storage = item_hashes(&pallet.storage);
+            quote! {
+                ::chameleon_core::compat::PalletHashes {
+                    name: #name,
+                    index: #index,
+                    hash: #hash,
+                    calls: #calls,
+                    events: #events,
+                    errors: #errors,
+                    storage: #storage,
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    Ok(quote! {
+        /// The structural hashes of the pallets, calls, events, errors and storage entries of the
+        /// metadata this module was generated from.
+        pub static METADATA_HASHES: &[::chameleon_core::compat::PalletHashes] = &[ #( #pallets ),* ];
+
+        /// Check whether the metadata of a node, e.g. the response to a `state_getMetadata`
+        /// request, still matches the types this module was generated from.
+        pub fn check_compatibility(
+            metadata: &[u8],
+        ) -> Result<(), ::chameleon_core::compat::Incompatibilities> {
+            ::chameleon_core::compat::check_compatibility(metadata, METADATA_HASHES)
+        }
+
+        /// Check the compatibility of the metadata of a node like `check_compatibility`, and
+        /// return the names of the pallets which changed without breaking the generated code, e.g.
+        /// with a new event or a changed constant.
+        pub fn changed_pallets(
+            metadata: &[u8],
+        ) -> Result<Vec<String>, ::chameleon_core::compat::Incompatibilities> {
+            ::chameleon_core::compat::changed_pallets(metadata, METADATA_HASHES)
+        }
+    })
+}
+
+/// Generate the `Error` enum of a pallet from its variant type in the type registry, or nothing
+/// if the pallet has no errors.
+///
+/// This is generated in the pallet module rather than reusing the generated type, which contains
+/// the `__Ignore` marker variant skipped by the runtime's encoding of the error.
+fn generate_error(
+    type_gen: &TypeGenerator,
+    type_registry: &PortableRegistry,
+    error: &PalletErrorMetadata<PortableForm>,
+) -> Result<Option<TokenStream2>> {
+    let id = error.ty.id();
+    let ty = type_registry
+        .resolve(id)
+        .ok_or(Error::TypeNotFound { id: id.get() })?;
+    let variants = match ty.type_def() {
+        TypeDef::Variant(variant) => variant
+            .variants()
+            .iter()
+            .filter(|v| v.name() != "__Ignore")
+            .collect::<Vec<_>>(),
+        _ => return Err(Error::ExpectedVariantType { id: id.get() }),
+    };
+    if variants.is_empty() {
+        return Ok(None);
+    }
+
+    let names = variants
+        .iter()
+        .map(|v| format_ident!("{}", v.name()))
+        .collect::<Vec<_>>();
+    let name_strs = variants.iter().map(|v| v.name());
+    // the position skips the `__Ignore` variant, which isn't counted by the runtime
+    let indices = variants
+        .iter()
+        .enumerate()
+        .map(|(position, v)| {
+            let index = variant_index(position, v)?;
+            Ok(proc_macro2::Literal::u8_unsuffixed(index))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let fields = variants
+        .iter()
+        .map(|v| generate_error_fields(type_gen, ty.path(), v.fields()))
+        .collect::<Result<Vec<_>>>()?;
+    let patterns = variants.iter().map(|v| match v.fields().first() {
+        None => quote! {},
+        Some(field) if field.name().is_some() => quote! { { .. } },
+        Some(_) => quote! { (..) },
+    });
+    let patterns = patterns.collect::<Vec<_>>();
+    let docs = variants
+        .iter()
+        .map(|v| {
+            v.docs()
+                .iter()
+                .map(|doc| doc.trim())
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .collect::<Vec<_>>();
+    let doc_attrs = variants.iter().map(|v| {
+        let docs = v.docs();
+        quote! { #( #[doc = #docs] )* }
+    });
+    let derives = type_gen.derives();
+
+    Ok(Some(quote! {
+        #derives
+        pub enum Error {
+            #(
+                #doc_attrs
+                #[codec(index = #indices)]
+                #names #fields,
+            )*
+        }
+
+        impl Error {
+            /// Returns the name of the error variant.
+            pub fn name(&self) -> &'static str {
+                match self {
+                    #( Self::#names #patterns => #name_strs, )*
+                }
+            }
+
+            /// Returns the documentation of the error variant.
+            pub fn docs(&self) -> &'static str {
+                match self {
+                    #( Self::#names #patterns => #docs, )*
+                }
+            }
+        }
+    }))
+}
+
+/// Generate the fields of an error variant, which are either all named or all unnamed.
+fn generate_error_fields(
+    type_gen: &TypeGenerator,
+    type_path: &scale_info::Path<PortableForm>,
+    fields: &[Field<PortableForm>],
+) -> Result<TokenStream2> {
+    if fields.is_empty() {
+        return Ok(quote! {});
+    }
+    let fields_tokens = fields
+        .iter()
+        .map(|field| {
+            let ty = type_gen.resolve_type_path(field.ty().id(), &[])?;
+            let (attrs, ty) = ty.field_type();
+            let name = field.name().map(|name| {
+                let name = format_ident!("{}", name);
+                quote! { #name: }
+            });
+            Ok(quote! { #attrs #name #ty })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if fields.iter().all(|f| f.name().is_some()) {
+        Ok(quote! { { #( #fields_tokens ),* } })
+    } else if fields.iter().all(|f| f.name().is_none()) {
+        Ok(quote! { ( #( #fields_tokens ),* ) })
+    } else {
+        Err(Error::MixedFieldNames {
+            type_path: type_path.segments().join("::"),
+        })
     }
 }
@@ -0,0 +1,312 @@
+use crate::{Error, Result, TokenStream2, TypeGenerator};
+use heck::SnakeCase as _;
+use proc_macro2::Ident;
+use quote::{format_ident, quote};
+use scale_info::{prelude::num::NonZeroU32, PortableRegistry};
+
+/// The runtime API methods to generate `state_call` functions for, which are not described by the
+/// metadata.
+///
+/// Types are given by their id in the type registry of the metadata, e.g. as shown by polkadot.js
+/// for V14 metadata.
+#[derive(Clone, Debug, Default)]
+pub struct RuntimeApis {
+    methods: Vec<RuntimeApiMethod>,
+}
+
+/// A runtime API method, called with the `state_call` RPC.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuntimeApiMethod {
+    /// The name of the runtime API trait, e.g. `TransactionPaymentApi`.
+    pub api: String,
+    /// The name of the method, e.g. `query_info`.
+    pub method: String,
+    /// The names and type ids of the arguments.
+    pub inputs: Vec<(String, u32)>,
+    /// The type id of the returned value.
+    pub output: u32,
+}
+
+impl RuntimeApis {
+    /// Construct a new, empty [`RuntimeApis`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse the declarations of runtime API methods, with a
+    /// `<Api>_<method>(<arg>: <type id>, ..) -> <type id>` declaration on each line, e.g.
+    /// `AccountNonceApi_account_nonce(account: 0) -> 4`. Empty lines and lines starting with `#`
+    /// are ignored.
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut apis = Self::new();
+        for (i, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let method =
+                parse_method(line).ok_or(Error::InvalidRuntimeApiDeclaration { line: i + 1 })?;
+            apis.insert(method)?;
+        }
+        Ok(apis)
+    }
+
+    /// Add a runtime API method. Fails if a method with the same name was already added to the
+    /// same API.
+    pub fn insert(&mut self, method: RuntimeApiMethod) -> Result<()> {
+        if self
+            .methods
+            .iter()
+            .any(|m| m.api == method.api && m.method == method.method)
+        {
+            return Err(Error::DuplicateRuntimeApiMethod {
+                api: method.api,
+                method: method.method,
+            });
+        }
+        self.methods.push(method);
+        Ok(())
+    }
+
+    /// Returns whether no runtime API methods were declared.
+    pub fn is_empty(&self) -> bool {
+        self.methods.is_empty()
+    }
+}
+
+/// Parse a `<Api>_<method>(<arg>: <type id>, ..) -> <type id>` declaration.
+fn parse_method(line: &str) -> Option<RuntimeApiMethod> {
+    let (signature, output) = line.split_once("->")?;
+    let (name, inputs) = signature.trim().strip_suffix(')')?.split_once('(')?;
+    let (api, method) = name.trim().split_once('_')?;
+    let type_id = |id: &str| id.trim().parse::<u32>().ok();
+    let inputs = inputs
+        .split(',')
+        .map(str::trim)
+        .filter(|input| !input.is_empty())
+        .map(|input| {
+            let (name, ty) = input.split_once(':')?;
+            Some((name.trim().to_string(), type_id(ty)?))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let is_ident = |name: &str| syn::parse_str::<Ident>(name).is_ok();
+    if !is_ident(api) || !is_ident(method) || !inputs.iter().all(|(name, _)| is_ident(name)) {
+        return None;
+    }
+    Some(RuntimeApiMethod {
+        api: api.to_string(),
+        method: method.to_string(),
+        inputs,
+        output: type_id(output)?,
+    })
+}
+
+/// Generate the `runtime_api` module, with a module for each runtime API and a function for each of
+/// its methods, which returns the `StateCall` to make with the `state_call` RPC.
+///
+/// The `type_id_offset` is added to the declared type ids to get the ids of the `types` registry.
+pub fn generate_runtime_api(
+    type_gen: &TypeGenerator,
+    types_mod_ident: &Ident,
+    types: &PortableRegistry,
+    apis: &RuntimeApis,
+    type_id_offset: u32,
+) -> Result<TokenStream2> {
+    if apis.is_empty() {
+        return Ok(quote! {});
+    }
+
+    // the methods grouped by their API, in the order of the declarations
+    let mut api_names = Vec::<&str>::new();
+    for method in &apis.methods {
+        if !api_names.contains(&&*method.api) {
+            api_names.push(&method.api);
+        }
+    }
+    let api_mods = api_names
+        .iter()
+        .map(|api| {
+            let mod_name = format_ident!("{}", api.to_snake_case());
+            let methods = apis
+                .methods
+                .iter()
+                .filter(|method| method.api == *api)
+                .map(|method| generate_method(type_gen, types, method, type_id_offset))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(quote! {
+                pub mod #mod_name {
+                    use super::#types_mod_ident;
+                    use super::StateCall;
+
+                    #( #methods )*
+                }
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(quote! {
+        pub mod runtime_api {
+            use super::#types_mod_ident;
+
+            /// A call of a runtime API method with the `state_call` RPC, which returns an encoded
+            /// `R`.
+            #[derive(Debug, Clone, PartialEq, Eq)]
+            pub struct StateCall<R> {
+                /// The name of the method to call, e.g. `AccountNonceApi_account_nonce`.
+                pub method: &'static str,
+                /// The SCALE encoded arguments of the method.
+                pub args: Vec<u8>,
+                response: ::core::marker::PhantomData<fn() -> R>,
+            }
+
+            impl<R: ::codec::Decode> StateCall<R> {
+                /// Create the call of a method with its encoded arguments.
+                pub fn new(method: &'static str, args: Vec<u8>) -> Self {
+                    Self {
+                        method,
+                        args,
+                        response: ::core::marker::PhantomData,
+                    }
+                }
+
+                /// Decode the response to the `state_call` request, i.e. its result as bytes.
+                pub fn decode_response(&self, bytes: &[u8]) -> Result<R, ::codec::Error> {
+                    let mut input = bytes;
+                    let response = R::decode(&mut input)?;
+                    if !input.is_empty() {
+                        return Err("Unexpected bytes after the runtime API response".into());
+                    }
+                    Ok(response)
+                }
+            }
+
+            #( #api_mods )*
+        }
+    })
+}
+
+fn generate_method(
+    type_gen: &TypeGenerator,
+    types: &PortableRegistry,
+    method: &RuntimeApiMethod,
+    type_id_offset: u32,
+) -> Result<TokenStream2> {
+    let state_call_method = format!("{}_{}", method.api, method.method);
+    let type_path = |id: u32| {
+        let registry_id = id
+            .checked_add(type_id_offset)
+            .and_then(NonZeroU32::new)
+            .filter(|registry_id| types.resolve(*registry_id).is_some())
+            .ok_or_else(|| Error::UnknownRuntimeApiType {
+                method: state_call_method.clone(),
+                id,
+            })?;
+        type_gen.resolve_type_path(registry_id, &[])
+    };
+    let fn_name = format_ident!("{}", method.method.to_snake_case());
+    let doc = format!(" Call the `{}` runtime API method.", state_call_method);
+    let arg_names = method
+        .inputs
+        .iter()
+        .map(|(name, _)| format_ident!("{}", name))
+        .collect::<Vec<_>>();
+    let arg_types = method
+        .inputs
+        .iter()
+        .map(|(_, ty)| type_path(*ty))
+        .collect::<Result<Vec<_>>>()?;
+    let output = type_path(method.output)?;
+
+    Ok(quote! {
+        #[doc = #doc]
+        pub fn #fn_name( #( #arg_names: #arg_types ),* ) -> StateCall<#output> {
+            #[allow(unused_mut)]
+            let mut args = Vec::new();
+            #( ::codec::Encode::encode_to(&#arg_names, &mut args); )*
+            StateCall::new(#state_call_method, args)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_runtime_api_declarations() {
+        let apis = RuntimeApis::parse(
+            "
+            # <Api>_<method>(<arg>: <type id>, ..) -> <type id>
+            TransactionPaymentApi_query_info(uxt: 34, len: 5) -> 13
+            Metadata_metadata() -> 25
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            apis.methods,
+            [
+                RuntimeApiMethod {
+                    api: "TransactionPaymentApi".into(),
+                    method: "query_info".into(),
+                    inputs: vec![("uxt".into(), 34), ("len".into(), 5),],
+                    output: 13,
+                },
+                RuntimeApiMethod {
+                    api: "Metadata".into(),
+                    method: "metadata".into(),
+                    inputs: vec![],
+                    output: 25,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_runtime_api_declarations() {
+        for source in &[
+            "AccountNonceApi_account_nonce(account: 1)",
+            "AccountNonceApi_account_nonce(account) -> 5",
+            "AccountNonceApi_account_nonce(the account: 1) -> 5",
+            "AccountNonceApi_account_nonce(account: -1) -> 5",
+            "AccountNonceApi(account: 1) -> 5",
+        ] {
+            assert!(matches!(
+                RuntimeApis::parse(&format!("# comment\n{}", source)),
+                Err(Error::InvalidRuntimeApiDeclaration { line: 2 })
+            ));
+        }
+    }
+
+    #[test]
+    fn duplicate_runtime_api_declarations() {
+        let result = RuntimeApis::parse(
+            "
+            AccountNonceApi_account_nonce(account: 1) -> 5
+            AccountNonceApi_account_nonce(who: 1) -> 6
+            ",
+        );
+
+        assert!(matches!(
+            result,
+            Err(Error::DuplicateRuntimeApiMethod { api, method })
+                if api == "AccountNonceApi" && method == "account_nonce"
+        ));
+    }
+
+    #[test]
+    fn same_method_of_different_apis() {
+        let mut apis = RuntimeApis::new();
+        for api in &["AccountNonceApi", "OtherNonceApi"] {
+            apis.insert(RuntimeApiMethod {
+                api: (*api).into(),
+                method: "account_nonce".into(),
+                inputs: vec![("account".into(), 0)],
+                output: 4,
+            })
+            .unwrap();
+        }
+
+        assert_eq!(apis.methods.len(), 2);
+    }
+}
@@ -0,0 +1,253 @@
+use crate::{Result, TokenStream2, TypeGenerator};
+use frame_metadata::v13::{
+    PalletStorageMetadata, StorageEntryMetadata, StorageEntryModifier, StorageEntryType,
+    StorageHasher,
+};
+use proc_macro2::Ident;
+use quote::{format_ident, quote};
+use scale_info::{form::PortableForm, prelude::num::NonZeroU32, PortableRegistry, TypeDef};
+use std::hash::Hasher as _;
+
+/// Generate the types shared by the storage entries of all pallets, which are placed in the root
+/// of the runtime module.
+///
+/// Hashing storage keys at runtime requires the `sp-core-hashing` crate.
+pub fn generate_storage_types() -> TokenStream2 {
+    quote! {
+        /// A hashing algorithm used to build storage keys.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub enum StorageHasher {
+            Blake2_128,
+            Blake2_256,
+            Blake2_128Concat,
+            Twox128,
+            Twox256,
+            Twox64Concat,
+            Identity,
+        }
+
+        impl StorageHasher {
+            /// Hash the SCALE encoded storage key.
+            pub fn hash(&self, bytes: &[u8]) -> Vec<u8> {
+                match self {
+                    Self::Blake2_128 => ::sp_core_hashing::blake2_128(bytes).to_vec(),
+                    Self::Blake2_256 => ::sp_core_hashing::blake2_256(bytes).to_vec(),
+                    Self::Blake2_128Concat => {
+                        [&::sp_core_hashing::blake2_128(bytes)[..], bytes].concat()
+                    }
+                    Self::Twox128 => ::sp_core_hashing::twox_128(bytes).to_vec(),
+                    Self::Twox256 => ::sp_core_hashing::twox_256(bytes).to_vec(),
+                    Self::Twox64Concat => [&::sp_core_hashing::twox_64(bytes)[..], bytes].concat(),
+                    Self::Identity => bytes.to_vec(),
+                }
+            }
+        }
+
+        /// A storage item of a pallet, which knows how to build its full storage key and decode
+        /// its value.
+        pub trait StorageEntry {
+            /// The storage prefix of the pallet.
+            const PALLET: &'static str;
+            /// The name of the storage item.
+            const STORAGE: &'static str;
+            /// `twox_128(PALLET) ++ twox_128(STORAGE)`.
+            const PREFIX: [u8; 32];
+            /// The hashers applied to each of the keys, in order.
+            const HASHERS: &'static [StorageHasher];
+            /// The SCALE encoded value returned if there is no value stored under the key.
+            const DEFAULT: Option<&'static [u8]>;
+            /// The type of the stored value.
+            type Value: ::codec::Decode;
+
+            /// Returns the SCALE encoded keys, one for each of the `HASHERS`.
+            fn encoded_keys(&self) -> Vec<Vec<u8>>;
+
+            /// Returns the full storage key, i.e. the prefix followed by the hashed keys.
+            fn key(&self) -> Vec<u8> {
+                let mut key = Self::PREFIX.to_vec();
+                for (hasher, encoded) in Self::HASHERS.iter().zip(self.encoded_keys()) {
+                    key.extend(hasher.hash(&encoded));
+                }
+                key
+            }
+
+            /// Decode the value as returned from storage, falling back to the default value if
+            /// there is none.
+            fn decode_value(bytes: Option<&[u8]>) -> Result<Option<Self::Value>, ::codec::Error> {
+                match bytes.or(Self::DEFAULT) {
+                    Some(mut bytes) => ::codec::Decode::decode(&mut bytes).map(Some),
+                    None => Ok(None),
+                }
+            }
+        }
+    }
+}
+
+/// Generate the `storage` module of a pallet, with a type for each of its storage entries.
+pub fn generate_storage(
+    type_gen: &TypeGenerator,
+    type_registry: &PortableRegistry,
+    types_mod_ident: &Ident,
+    storage: &PalletStorageMetadata<PortableForm>,
+) -> Result<TokenStream2> {
+    let entries = storage
+        .entries
+        .iter()
+        .map(|entry| generate_storage_entry(type_gen, type_registry, &storage.prefix, entry))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(quote! {
+        pub mod storage {
+            use super::#types_mod_ident;
+            #( #entries )*
+        }
+    })
+}
+
+fn generate_storage_entry(
+    type_gen: &TypeGenerator,
+    type_registry: &PortableRegistry,
+    pallet_prefix: &str,
+    entry: &StorageEntryMetadata<PortableForm>,
+) -> Result<TokenStream2> {
+    let (keys, hashers, value) = storage_entry_types(type_registry, entry);
+
+    let name = format_ident!("{}", entry.name);
+    let storage_name = &entry.name;
+    let docs = &entry.documentation;
+    let key_types = keys
+        .iter()
+        .map(|key| type_gen.resolve_type_path(*key, &[]))
+        .collect::<Result<Vec<_>>>()?;
+    let encoded_keys = (0..keys.len()).map(|i| {
+        let index = syn::Index::from(i);
+        quote! { ::codec::Encode::encode(&self.#index) }
+    });
+    let hashers = hashers.iter().map(|hasher| {
+        let hasher = match hasher {
+            StorageHasher::Blake2_128 => quote! { Blake2_128 },
+            StorageHasher::Blake2_256 => quote! { Blake2_256 },
+            StorageHasher::Blake2_128Concat => quote! { Blake2_128Concat },
+            StorageHasher::Twox128 => quote! { Twox128 },
+            StorageHasher::Twox256 => quote! { Twox256 },
+            StorageHasher::Twox64Concat => quote! { Twox64Concat },
+            StorageHasher::Identity => quote! { Identity },
+        };
+        quote! { super::super::StorageHasher::#hasher }
+    });
+    let value_type = type_gen.resolve_type_path(value, &[])?;
+    let prefix = storage_prefix(pallet_prefix, storage_name).to_vec();
+    let default = match entry.modifier {
+        StorageEntryModifier::Optional => quote! { None },
+        StorageEntryModifier::Default => {
+            let default = &entry.default;
+            quote! { Some(&[ #( #default ),* ]) }
+        }
+    };
+    let key_fields = if key_types.is_empty() {
+        quote! { ; }
+    } else {
+        quote! { ( #( pub #key_types ),* ); }
+    };
+
+    Ok(quote! {
+        #( #[doc = #docs] )*
+        pub struct #name #key_fields
+
+        impl super::super::StorageEntry for #name {
+            const PALLET: &'static str = #pallet_prefix;
+            const STORAGE: &'static str = #storage_name;
+            const PREFIX: [u8; 32] = [ #( #prefix ),* ];
+            const HASHERS: &'static [super::super::StorageHasher] = &[ #( #hashers ),* ];
+            const DEFAULT: Option<&'static [u8]> = #default;
+            type Value = #value_type;
+
+            fn encoded_keys(&self) -> Vec<Vec<u8>> {
+                vec![ #( #encoded_keys ),* ]
+            }
+        }
+    })
+}
+
+/// Returns the key types, the hashers of the keys and the value type of a storage entry.
+pub fn storage_entry_types<'a>(
+    type_registry: &PortableRegistry,
+    entry: &'a StorageEntryMetadata<PortableForm>,
+) -> (Vec<NonZeroU32>, Vec<&'a StorageHasher>, NonZeroU32) {
+    match entry.ty {
+        StorageEntryType::Plain(ref value) => (Vec::new(), Vec::new(), value.id()),
+        StorageEntryType::Map {
+            ref hasher,
+            ref key,
+            ref value,
+            ..
+        } => (vec![key.id()], vec![hasher], value.id()),
+        StorageEntryType::DoubleMap {
+            ref hasher,
+            ref key1,
+            ref key2,
+            ref value,
+            ref key2_hasher,
+        } => (
+            vec![key1.id(), key2.id()],
+            vec![hasher, key2_hasher],
+            value.id(),
+        ),
+        StorageEntryType::NMap {
+            ref keys,
+            ref hashers,
+            ref value,
+        } => {
+            // the keys are represented as a single tuple type
+            let keys = match type_registry.resolve(keys.id()).map(|ty| ty.type_def()) {
+                Some(TypeDef::Tuple(tuple)) => tuple.fields().iter().map(|f| f.id()).collect(),
+                _ => vec![keys.id()],
+            };
+            (keys, hashers.iter().collect(), value.id())
+        }
+    }
+}
+
+/// Returns `twox_128(pallet_prefix) ++ twox_128(storage_name)`, the prefix of all keys of a
+/// storage entry.
+fn storage_prefix(pallet_prefix: &str, storage_name: &str) -> [u8; 32] {
+    let mut prefix = [0u8; 32];
+    prefix[..16].copy_from_slice(&twox_128(pallet_prefix.as_bytes()));
+    prefix[16..].copy_from_slice(&twox_128(storage_name.as_bytes()));
+    prefix
+}
+
+pub fn twox_128(data: &[u8]) -> [u8; 16] {
+    let mut hash = [0u8; 16];
+    for (seed, chunk) in hash.chunks_mut(8).enumerate() {
+        let mut hasher = twox_hash::XxHash64::with_seed(seed as u64);
+        hasher.write(data);
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn storage_prefix_is_twox_128_of_pallet_and_storage_names() {
+        let prefix = storage_prefix("System", "Account");
+
+        assert_eq!(
+            prefix[..16],
+            [
+                0x26, 0xaa, 0x39, 0x4e, 0xea, 0x56, 0x30, 0xe0, 0x7c, 0x48, 0xae, 0x0c, 0x95, 0x58,
+                0xce, 0xf7
+            ]
+        );
+        assert_eq!(
+            prefix[16..],
+            [
+                0xb9, 0x9d, 0x88, 0x0e, 0xc6, 0x81, 0x79, 0x9c, 0x0c, 0xf3, 0x0e, 0x88, 0x86, 0x37,
+                0x1d, 0xa9
+            ]
+        );
+    }
+}
@@ -12,59 +12,244 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::{Error, LegacyTypeRegistry, Result, RuntimeApis};
+use frame_metadata::v13::PalletCallMetadata;
 use proc_macro2::{Ident, Span, TokenStream as TokenStream2, TokenStream};
 use quote::{format_ident, quote, ToTokens};
 use scale_info::{
     form::PortableForm, prelude::num::NonZeroU32, Field, PortableRegistry, Type, TypeDef,
-    TypeDefPrimitive,
+    TypeDefPrimitive, Variant,
 };
-use std::collections::{BTreeMap, HashSet};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    convert::TryFrom,
+};
+
+/// Configuration for the code generated by the [`TypeGenerator`].
+#[derive(Clone, Debug)]
+pub struct TypeGeneratorConfig {
+    /// Map `U256` primitives onto `primitive_types::U256`, instead of generating a self-contained
+    /// 256-bit integer type. Requires the `primitive-types` crate with the `codec` feature.
+    pub primitive_types: bool,
+    /// The derives of all generated types, `Debug`, `Encode` and `Decode` by default.
+    pub derives: Vec<syn::Path>,
+    /// Additional derives for the types at the given paths, e.g. `sp_core::crypto::AccountId32`,
+    /// or `U256` and `I256` for the generated 256-bit integers. The types generated from legacy
+    /// (V12) metadata are given by their path in the runtime module, e.g. `balances::Call`.
+    pub type_derives: HashMap<String, Vec<syn::Path>>,
+    /// Existing Rust types to use instead of generating the types at the given paths. Any type
+    /// parameters are passed on to the substitute type. Bit sequences are mapped onto
+    /// `bitvec::vec::BitVec` by default.
+    pub type_substitutes: HashMap<String, syn::Path>,
+    /// The Rust types of the type names in legacy (V12) metadata, which has no type registry.
+    pub legacy_types: LegacyTypeRegistry,
+    /// Embed the structural hashes of the pallets and their items in the generated
+    /// code, with a `check_compatibility` function comparing them with the metadata of a node.
+    /// Requires the `chameleon-core` crate.
+    pub compatibility_check: bool,
+    /// Generate `EventRecords::decode_tolerant`, which decodes the events of a block with the
+    /// metadata of a node, keeping the bytes of events unknown to the generated code instead of
+    /// failing. Requires the `chameleon-core` crate.
+    pub tolerant_events: bool,
+    /// The runtime API methods to generate `state_call` functions for, in the `runtime_api`
+    /// module.
+    pub runtime_apis: RuntimeApis,
+    /// The types of the additional signed data of signed extensions by their identifier, which
+    /// isn't described by the metadata. Only required for signed extensions unknown to the
+    /// generator.
+    pub additional_signed_types: HashMap<String, syn::Type>,
+}
+
+impl Default for TypeGeneratorConfig {
+    fn default() -> Self {
+        Self {
+            primitive_types: false,
+            derives: vec![
+                syn::parse_quote!(Debug),
+                syn::parse_quote!(::codec::Encode),
+                syn::parse_quote!(::codec::Decode),
+            ],
+            type_derives: HashMap::new(),
+            type_substitutes: [
+                (
+                    "bitvec::vec::BitVec",
+                    syn::parse_quote!(::bitvec::vec::BitVec),
+                ),
+                (
+                    "bitvec::order::Lsb0",
+                    syn::parse_quote!(::bitvec::order::Lsb0),
+                ),
+                (
+                    "bitvec::order::Msb0",
+                    syn::parse_quote!(::bitvec::order::Msb0),
+                ),
+            ]
+            .iter()
+            .cloned()
+            .map(|(type_path, substitute)| (type_path.to_string(), substitute))
+            .collect(),
+            legacy_types: LegacyTypeRegistry::default(),
+            compatibility_check: false,
+            tolerant_events: false,
+            runtime_apis: RuntimeApis::default(),
+            additional_signed_types: HashMap::new(),
+        }
+    }
+}
+
+impl TypeGeneratorConfig {
+    /// Add a derive to all generated types.
+    pub fn derive(mut self, derive: syn::Path) -> Self {
+        self.derives.push(derive);
+        self
+    }
+
+    /// Add a derive to the type at the given path, e.g. `sp_core::crypto::AccountId32`.
+    pub fn derive_for_type(mut self, type_path: impl Into<String>, derive: syn::Path) -> Self {
+        self.type_derives
+            .entry(type_path.into())
+            .or_default()
+            .push(derive);
+        self
+    }
+
+    /// Use the existing Rust type at `substitute` instead of generating the type at the given path,
+    /// e.g. `sp_core::crypto::AccountId32`.
+    pub fn substitute_type(mut self, type_path: impl Into<String>, substitute: syn::Path) -> Self {
+        self.type_substitutes.insert(type_path.into(), substitute);
+        self
+    }
+
+    /// Use `ty` as the type of the additional signed data of the signed extension with the given
+    /// identifier, e.g. `()` for a signed extension without additional signed data.
+    pub fn additional_signed_type(mut self, identifier: impl Into<String>, ty: syn::Type) -> Self {
+        self.additional_signed_types.insert(identifier.into(), ty);
+        self
+    }
+
+    /// Returns the substitute for the type at the given path, if any.
+    fn type_substitute(&self, type_path: &scale_info::Path<PortableForm>) -> Option<&syn::Path> {
+        if type_path.segments().is_empty() {
+            return None;
+        }
+        self.type_substitutes.get(&type_path.segments().join("::"))
+    }
+
+    /// Add a derive to the type at the given path, unless the type already derives it.
+    fn require_derive(&mut self, type_path: String, derive: syn::Path) {
+        let ident = |path: &syn::Path| path.segments.last().map(|segment| segment.ident.clone());
+        let type_derives = self.type_derives.entry(type_path).or_default();
+        if !self
+            .derives
+            .iter()
+            .chain(type_derives.iter())
+            .any(|existing| ident(existing) == ident(&derive))
+        {
+            type_derives.push(derive);
+        }
+    }
+
+    /// Returns the `#[derive(..)]` attribute for the type at the given path.
+    pub(crate) fn derives_for_type(&self, type_path: &str) -> TokenStream2 {
+        let type_derives = self
+            .type_derives
+            .get(type_path)
+            .map_or(&[][..], |derives| &derives[..]);
+        let derives = self.derives.iter().chain(type_derives);
+        quote! { #[derive( #( #derives ),* )] }
+    }
+}
 
 #[derive(Debug)]
 pub struct TypeGenerator<'a> {
     root_mod_ident: Ident,
     type_registry: &'a PortableRegistry,
+    config: TypeGeneratorConfig,
 }
 
 impl<'a> TypeGenerator<'a> {
     /// Construct a new [`TypeGenerator`].
     pub fn new(type_registry: &'a PortableRegistry, root_mod: &'static str) -> Self {
+        Self::with_config(type_registry, root_mod, TypeGeneratorConfig::default())
+    }
+
+    /// Construct a new [`TypeGenerator`] with the given configuration.
+    pub fn with_config(
+        type_registry: &'a PortableRegistry,
+        root_mod: &'static str,
+        mut config: TypeGeneratorConfig,
+    ) -> Self {
         let root_mod_ident = Ident::new(root_mod, Span::call_site());
+        for type_path in compact_as_types(type_registry) {
+            config.require_derive(type_path, syn::parse_quote!(::codec::CompactAs));
+        }
+        for type_path in btree_map_key_types(type_registry) {
+            for derive in &["PartialEq", "Eq", "PartialOrd", "Ord"] {
+                let derive = Ident::new(derive, Span::call_site());
+                config.require_derive(type_path.clone(), syn::parse_quote!(#derive));
+            }
+        }
         Self {
             root_mod_ident,
             type_registry,
+            config,
         }
     }
 
+    /// Returns the `#[derive(..)]` attribute of all generated types, for the types which are
+    /// generated outside of the type registry, e.g. the outer `Call` enum.
+    pub fn derives(&self) -> TokenStream2 {
+        let derives = &self.config.derives;
+        quote! { #[derive( #( #derives ),* )] }
+    }
+
     /// Generate a module containing all types defined in the supplied type registry.
-    pub fn generate_types_mod(&'a self) -> Module<'a> {
+    pub fn generate_types_mod(&self) -> Result<Module> {
         let mut root_mod = Module::new(self.root_mod_ident.clone(), self.root_mod_ident.clone());
 
         for (id, ty) in self.type_registry.enumerate() {
+            match ty.type_def() {
+                TypeDef::Primitive(TypeDefPrimitive::U256) if !self.config.primitive_types => {
+                    root_mod.items.push(int_256_type(
+                        "U256",
+                        false,
+                        &self.config.derives_for_type("U256"),
+                    ))
+                }
+                TypeDef::Primitive(TypeDefPrimitive::I256) => root_mod.items.push(int_256_type(
+                    "I256",
+                    true,
+                    &self.config.derives_for_type("I256"),
+                )),
+                _ => (),
+            }
             if ty.path().namespace().is_empty() {
                 // prelude types e.g. Option/Result have no namespace, so we don't generate them
                 continue;
             }
+            if self.config.type_substitute(ty.path()).is_some() {
+                continue;
+            }
             self.insert_type(
                 ty.clone(),
                 id,
                 ty.path().namespace().to_vec(),
                 &self.root_mod_ident,
                 &mut root_mod,
-            )
+            )?
         }
 
-        root_mod
+        Ok(root_mod)
     }
 
     fn insert_type(
-        &'a self,
+        &self,
         ty: Type<PortableForm>,
         id: NonZeroU32,
         path: Vec<String>,
         root_mod_ident: &Ident,
-        module: &mut Module<'a>,
-    ) {
+        module: &mut Module,
+    ) -> Result<()> {
         let segment = path.first().expect("path has at least one segment");
         let mod_ident = Ident::new(segment, Span::call_site());
 
@@ -74,39 +259,40 @@ impl<'a> TypeGenerator<'a> {
             .or_insert_with(|| Module::new(mod_ident, root_mod_ident.clone()));
 
         if path.len() == 1 {
-            child_mod
-                .types
-                .insert(ty.path().clone(), ModuleType { ty, type_gen: self });
+            let type_path = ty.path().clone();
+            let tokens = ModuleType { ty, type_gen: self }.generate()?;
+            child_mod.types.insert(type_path, tokens);
+            Ok(())
         } else {
             self.insert_type(ty, id, path[1..].to_vec(), root_mod_ident, child_mod)
         }
     }
 
-    /// # Panics
+    /// # Errors
     ///
-    /// If no type with the given id found in the type registry.
+    /// If no type with the given id found in the type registry, or the type is not supported.
     pub fn resolve_type_path(
         &self,
         id: NonZeroU32,
         parent_type_params: &[TypeParameter],
-    ) -> TypePath {
+    ) -> Result<TypePath> {
         if let Some(parent_type_param) = parent_type_params
             .iter()
             .find(|tp| tp.concrete_type_id == id)
         {
-            return TypePath::Parameter(parent_type_param.clone());
+            return Ok(TypePath::Parameter(parent_type_param.clone()));
         }
 
-        let resolve_type = |id| {
-            self.type_registry
-                .resolve(id)
-                .unwrap_or_else(|| panic!("No type with id {} found", id))
-                .clone()
-        };
+        let (_, ty) = resolve_type(self.type_registry, id)?;
+        let ty = ty.clone();
 
-        let mut ty = resolve_type(id);
-        if ty.path().ident() == Some("Cow".to_string()) {
-            ty = resolve_type(ty.type_params()[0].id())
+        if let TypeDef::Primitive(TypeDefPrimitive::U256) = ty.type_def() {
+            if self.config.primitive_types {
+                return Ok(TypePath::Substitute(TypePathSubstitute {
+                    path: syn::parse_quote! { ::primitive_types::U256 },
+                    params: Vec::new(),
+                }));
+            }
         }
 
         let params_type_ids = match ty.type_def() {
@@ -121,30 +307,98 @@ impl<'a> TypeGenerator<'a> {
         let params = params_type_ids
             .iter()
             .map(|tp| self.resolve_type_path(*tp, parent_type_params))
-            .collect::<Vec<_>>();
+            .collect::<Result<Vec<_>>>()?;
 
-        TypePath::Type(TypePathType {
+        if let Some(substitute) = self.config.type_substitute(ty.path()) {
+            return Ok(TypePath::Substitute(TypePathSubstitute {
+                path: substitute.clone(),
+                params,
+            }));
+        }
+
+        Ok(TypePath::Type(TypePathType {
             ty,
             params,
             root_mod_ident: self.root_mod_ident.clone(),
-        })
+        }))
+    }
+}
+
+/// Resolve the type with the given id, looking through a `Cow<T>` to the `T` it is encoded as.
+/// Returns the id of the resolved type along with the type.
+///
+/// # Errors
+///
+/// If no type with the given id is found in the type registry.
+pub(crate) fn resolve_type(
+    type_registry: &PortableRegistry,
+    id: NonZeroU32,
+) -> Result<(NonZeroU32, &Type<PortableForm>)> {
+    let resolve = |id: NonZeroU32| {
+        type_registry
+            .resolve(id)
+            .ok_or(Error::TypeNotFound { id: id.get() })
+    };
+    let ty = resolve(id)?;
+    if ty.path().ident() == Some("Cow".to_string()) {
+        let id = ty.type_params()[0].id();
+        return Ok((id, resolve(id)?));
     }
+    Ok((id, ty))
+}
+
+/// The index of an encoded variant, i.e. the `#[codec(index)]` of the generated enum: its
+/// discriminant if it has one, or else its position.
+///
+/// # Errors
+///
+/// If the discriminant doesn't fit in the `u8` index of an encoded variant.
+pub(crate) fn variant_index(position: usize, variant: &Variant<PortableForm>) -> Result<u8> {
+    let index = variant.discriminant().unwrap_or(position as u64);
+    u8::try_from(index).map_err(|_| Error::InvalidVariantIndex {
+        variant: variant.name().clone(),
+        index,
+    })
+}
+
+/// Returns the index of the call with the given name, from the variant of the call type.
+pub(crate) fn call_index(
+    types: &PortableRegistry,
+    calls: &PalletCallMetadata<PortableForm>,
+    name: &str,
+) -> Result<u8> {
+    let (id, ty) = resolve_type(types, calls.ty.id())?;
+    let variants = match ty.type_def() {
+        TypeDef::Variant(variant) => variant.variants(),
+        _ => return Err(Error::ExpectedVariantType { id: id.get() }),
+    };
+    let (position, variant) = variants
+        .iter()
+        .enumerate()
+        .find(|(_, variant)| variant.name() == name)
+        .ok_or_else(|| Error::UnknownVariant {
+            id: id.get(),
+            variant: name.to_string(),
+        })?;
+    variant_index(position, variant)
 }
 
 #[derive(Debug)]
-pub struct Module<'a> {
+pub struct Module {
     name: Ident,
     root_mod: Ident,
-    children: BTreeMap<Ident, Module<'a>>,
-    types: BTreeMap<scale_info::Path<scale_info::form::PortableForm>, ModuleType<'a>>,
+    children: BTreeMap<Ident, Module>,
+    types: BTreeMap<scale_info::Path<scale_info::form::PortableForm>, TokenStream2>,
+    items: Vec<TokenStream2>,
 }
 
-impl<'a> ToTokens for Module<'a> {
+impl ToTokens for Module {
     fn to_tokens(&self, tokens: &mut TokenStream) {
         let name = &self.name;
         let root_mod = &self.root_mod;
         let modules = self.children.values();
         let types = self.types.values().clone();
+        let items = &self.items;
 
         tokens.extend(quote! {
             pub mod #name {
@@ -152,23 +406,25 @@ impl<'a> ToTokens for Module<'a> {
 
                 #( #modules )*
                 #( #types )*
+                #( #items )*
             }
         })
     }
 }
 
-impl<'a> Module<'a> {
+impl Module {
     pub fn new(name: Ident, root_mod: Ident) -> Self {
         Self {
             name,
             root_mod,
             children: BTreeMap::new(),
             types: BTreeMap::new(),
+            items: Vec::new(),
         }
     }
 
     /// Returns the module with the given path, if any.
-    pub fn get_mod(&'a self, path_segs: &[&'static str]) -> Option<&'a Module<'a>> {
+    pub fn get_mod(&self, path_segs: &[&'static str]) -> Option<&Module> {
         let (mod_name, rest) = path_segs.split_first()?;
         let mod_ident = Ident::new(mod_name, Span::call_site());
         let module = self.children.get(&mod_ident)?;
@@ -191,8 +447,9 @@ pub struct ModuleType<'a> {
     ty: Type<PortableForm>,
 }
 
-impl<'a> quote::ToTokens for ModuleType<'a> {
-    fn to_tokens(&self, tokens: &mut TokenStream) {
+impl<'a> ModuleType<'a> {
+    /// Generate the type definition.
+    fn generate(&self) -> Result<TokenStream2> {
         let type_params = self
             .ty
             .type_params()
@@ -218,15 +475,21 @@ impl<'a> quote::ToTokens for ModuleType<'a> {
             syn::Type::Path(path)
         });
 
+        let docs = self.ty.docs();
+        let derives = self
+            .type_gen
+            .config
+            .derives_for_type(&self.ty.path().segments().join("::"));
         match self.ty.type_def() {
             TypeDef::Composite(composite) => {
                 let type_name = type_name.expect("structs should have a name");
-                let (fields, _) = self.composite_fields(composite.fields(), &type_params, true);
+                let (fields, _) = self.composite_fields(composite.fields(), &type_params, true)?;
                 let ty_toks = quote! {
-                    #[derive(Debug, ::codec::Encode, ::codec::Decode)]
+                    #( #[doc = #docs] )*
+                    #derives
                     pub struct #type_name #fields
                 };
-                tokens.extend(ty_toks);
+                Ok(ty_toks)
             }
             TypeDef::Variant(variant) => {
                 let type_name = type_name.expect("variants should have a name");
@@ -234,15 +497,28 @@ impl<'a> quote::ToTokens for ModuleType<'a> {
                 let mut used_type_params = HashSet::new();
                 let type_params_set: HashSet<_> = type_params.iter().cloned().collect();
 
-                for v in variant.variants() {
+                for (position, v) in variant.variants().iter().enumerate() {
                     let variant_name = format_ident!("{}", v.name());
+                    let variant_docs = v.docs();
                     let (fields, unused_type_params) = if v.fields().is_empty() {
                         let unused = type_params_set.iter().cloned().collect::<Vec<_>>();
                         (quote! {}, unused)
                     } else {
-                        self.composite_fields(v.fields(), &type_params, false)
+                        self.composite_fields(v.fields(), &type_params, false)?
                     };
-                    variants.push(quote! { #variant_name #fields });
+                    let index = match v.discriminant() {
+                        Some(_) => {
+                            let index = variant_index(position, v)?;
+                            let index = proc_macro2::Literal::u8_unsuffixed(index);
+                            Some(quote! { #[codec(index = #index)] })
+                        }
+                        None => None,
+                    };
+                    variants.push(quote! {
+                        #( #[doc = #variant_docs] )*
+                        #index
+                        #variant_name #fields
+                    });
                     let unused_params_set = unused_type_params.iter().cloned().collect();
                     let used_params = type_params_set.difference(&unused_params_set);
 
@@ -261,25 +537,24 @@ impl<'a> quote::ToTokens for ModuleType<'a> {
                 }
 
                 let ty_toks = quote! {
-                    #[derive(Debug, ::codec::Encode, ::codec::Decode)]
+                    #( #[doc = #docs] )*
+                    #derives
                     pub enum #type_name {
                         #( #variants, )*
                     }
                 };
-                tokens.extend(ty_toks);
+                Ok(ty_toks)
             }
-            _ => (), // all built-in types should already be in scope
+            _ => Ok(quote! {}), // all built-in types should already be in scope
         }
     }
-}
 
-impl<'a> ModuleType<'a> {
     fn composite_fields(
         &self,
         fields: &'a [Field<PortableForm>],
         type_params: &'a [TypeParameter],
         is_struct: bool,
-    ) -> (TokenStream2, Vec<TypeParameter>) {
+    ) -> Result<(TokenStream2, Vec<TypeParameter>)> {
         let named = fields.iter().all(|f| f.name().is_some());
         let unnamed = fields.iter().all(|f| f.name().is_none());
 
@@ -320,24 +595,26 @@ impl<'a> ModuleType<'a> {
                         format_ident!("{}", field.name().expect("named field without a name"));
                     let ty = self
                         .type_gen
-                        .resolve_type_path(field.ty().id(), type_params);
-                    (name, ty, field.type_name())
+                        .resolve_type_path(field.ty().id(), type_params)?;
+                    Ok((name, ty, field.type_name(), field.docs()))
                 })
-                .collect::<Vec<_>>();
+                .collect::<Result<Vec<_>>>()?;
 
             let mut fields_tokens = fields
                 .iter()
-                .map(|(name, ty, ty_name)| {
+                .map(|(name, ty, ty_name, docs)| {
+                    let (attrs, ty) = ty.field_type();
                     let ty = ty_toks(ty_name, ty);
                     if is_struct {
-                        quote! { pub #name: #ty }
+                        quote! { #( #[doc = #docs] )* #attrs pub #name: #ty }
                     } else {
-                        quote! { #name: #ty }
+                        quote! { #( #[doc = #docs] )* #attrs #name: #ty }
                     }
                 })
                 .collect::<Vec<_>>();
 
-            let unused_params = unused_type_params(type_params, fields.iter().map(|(_, ty, _)| ty));
+            let unused_params =
+                unused_type_params(type_params, fields.iter().map(|(_, ty, _, _)| ty));
 
             if is_struct && !unused_params.is_empty() {
                 fields_tokens.push(quote! {
@@ -350,31 +627,32 @@ impl<'a> ModuleType<'a> {
                     #( #fields_tokens, )*
                 }
             };
-            (fields, unused_params)
+            Ok((fields, unused_params))
         } else if unnamed {
             let type_paths = fields
                 .iter()
                 .map(|field| {
                     let ty = self
                         .type_gen
-                        .resolve_type_path(field.ty().id(), type_params);
-                    (ty, field.type_name())
+                        .resolve_type_path(field.ty().id(), type_params)?;
+                    Ok((ty, field.type_name(), field.docs()))
                 })
-                .collect::<Vec<_>>();
+                .collect::<Result<Vec<_>>>()?;
             let mut fields_tokens = type_paths
                 .iter()
-                .map(|(ty, ty_name)| {
+                .map(|(ty, ty_name, docs)| {
+                    let (attrs, ty) = ty.field_type();
                     let ty = ty_toks(ty_name, ty);
                     if is_struct {
-                        quote! { pub #ty }
+                        quote! { #( #[doc = #docs] )* #attrs pub #ty }
                     } else {
-                        quote! { #ty }
+                        quote! { #( #[doc = #docs] )* #attrs #ty }
                     }
                 })
                 .collect::<Vec<_>>();
 
             let unused_params =
-                unused_type_params(type_params, type_paths.iter().map(|(ty, _)| ty));
+                unused_type_params(type_params, type_paths.iter().map(|(ty, _, _)| ty));
 
             if is_struct && !unused_params.is_empty() {
                 fields_tokens
@@ -389,9 +667,11 @@ impl<'a> ModuleType<'a> {
                 fields
             };
 
-            (fields_tokens, unused_params)
+            Ok((fields_tokens, unused_params))
         } else {
-            panic!("Fields must be either all named or all unnamed")
+            Err(Error::MixedFieldNames {
+                type_path: self.ty.path().segments().join("::"),
+            })
         }
     }
 }
@@ -400,6 +680,7 @@ impl<'a> ModuleType<'a> {
 pub enum TypePath {
     Parameter(TypeParameter),
     Type(TypePathType),
+    Substitute(TypePathSubstitute),
 }
 
 impl quote::ToTokens for TypePath {
@@ -414,6 +695,19 @@ impl TypePath {
         match self {
             TypePath::Parameter(ty_param) => syn::Type::Path(syn::parse_quote! { #ty_param }),
             TypePath::Type(ty) => ty.to_syn_type(),
+            TypePath::Substitute(sub) => sub.to_syn_type(),
+        }
+    }
+
+    /// Returns the type to use for a field of this type, along with any field attributes.
+    ///
+    /// Fields of type `Compact<T>` are generated as `T` annotated with `#[codec(compact)]`.
+    pub fn field_type(&self) -> (Option<TokenStream2>, &TypePath) {
+        match self {
+            TypePath::Type(ty) if ty.is_compact() => {
+                (Some(quote! { #[codec(compact)] }), &ty.params[0])
+            }
+            _ => (None, self),
         }
     }
 
@@ -432,6 +726,7 @@ impl TypePath {
                 acc.insert(type_parameter.clone());
             }
             Self::Type(type_path) => type_path.parent_type_params(acc),
+            Self::Substitute(sub) => sub.parent_type_params(acc),
         }
     }
 }
@@ -444,6 +739,10 @@ pub struct TypePathType {
 }
 
 impl TypePathType {
+    fn is_compact(&self) -> bool {
+        matches!(self.ty.type_def(), TypeDef::Compact(_))
+    }
+
     fn to_syn_type(&self) -> syn::Type {
         let params = &self.params;
         match self.ty.type_def() {
@@ -484,7 +783,7 @@ impl TypePathType {
                 syn::Type::Tuple(tuple)
             }
             TypeDef::Primitive(primitive) => {
-                let primitive = match primitive {
+                let ident = match primitive {
                     TypeDefPrimitive::Bool => "bool",
                     TypeDefPrimitive::Char => "char",
                     TypeDefPrimitive::Str => "String",
@@ -493,16 +792,23 @@ impl TypePathType {
                     TypeDefPrimitive::U32 => "u32",
                     TypeDefPrimitive::U64 => "u64",
                     TypeDefPrimitive::U128 => "u128",
-                    TypeDefPrimitive::U256 => unimplemented!("not a rust primitive"),
+                    TypeDefPrimitive::U256 => "U256",
                     TypeDefPrimitive::I8 => "i8",
                     TypeDefPrimitive::I16 => "i16",
                     TypeDefPrimitive::I32 => "i32",
                     TypeDefPrimitive::I64 => "i64",
                     TypeDefPrimitive::I128 => "i128",
-                    TypeDefPrimitive::I256 => unimplemented!("not a rust primitive"),
+                    TypeDefPrimitive::I256 => "I256",
+                };
+                let ident = format_ident!("{}", ident);
+                let path = match primitive {
+                    TypeDefPrimitive::U256 | TypeDefPrimitive::I256 => {
+                        // not rust primitives, so are generated in the root module
+                        let root_mod_ident = &self.root_mod_ident;
+                        syn::parse_quote! { #root_mod_ident::#ident }
+                    }
+                    _ => syn::parse_quote! { #ident },
                 };
-                let ident = format_ident!("{}", primitive);
-                let path = syn::parse_quote! { #ident };
                 syn::Type::Path(path)
             }
             TypeDef::Phantom(_) => {
@@ -514,10 +820,9 @@ impl TypePathType {
                 syn::Type::Path(type_path)
             }
             TypeDef::Compact(_) => {
-                // todo: change the return type of this method to include info that it is compact
-                // and should be annotated with #[compact] for fields
+                // fields use `#[codec(compact)]` instead, see `TypePath::field_type`
                 let compact_type = &self.params[0];
-                syn::Type::Path(syn::parse_quote! ( #compact_type ))
+                syn::Type::Path(syn::parse_quote! ( ::codec::Compact<#compact_type> ))
             }
         }
     }
@@ -538,6 +843,31 @@ impl TypePathType {
     }
 }
 
+/// A type which is not generated, but replaced by an existing Rust type.
+#[derive(Debug)]
+pub struct TypePathSubstitute {
+    path: syn::Path,
+    params: Vec<TypePath>,
+}
+
+impl TypePathSubstitute {
+    fn to_syn_type(&self) -> syn::Type {
+        let path = &self.path;
+        let params = &self.params;
+        if params.is_empty() {
+            syn::Type::Path(syn::parse_quote! { #path })
+        } else {
+            syn::Type::Path(syn::parse_quote! { #path< #( #params ),* > })
+        }
+    }
+
+    fn parent_type_params(&self, acc: &mut HashSet<TypeParameter>) {
+        for p in &self.params {
+            p.parent_type_params(acc);
+        }
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct TypeParameter {
     concrete_type_id: NonZeroU32,
@@ -550,6 +880,141 @@ impl quote::ToTokens for TypeParameter {
     }
 }
 
+/// Returns the paths of the structs which are compact encoded, e.g. `Perbill`. They derive
+/// `CompactAs` to be compact encoded as their single field.
+fn compact_as_types(type_registry: &PortableRegistry) -> Vec<String> {
+    let mut paths = Vec::new();
+    for (_, ty) in type_registry.enumerate() {
+        let mut id = match ty.type_def() {
+            TypeDef::Compact(compact) => compact.type_param().id(),
+            _ => continue,
+        };
+        // the single field may itself be a struct with a single field
+        while let Some(ty) = type_registry.resolve(id) {
+            match ty.type_def() {
+                TypeDef::Composite(composite) if composite.fields().len() == 1 => {
+                    let type_path = ty.path().segments().join("::");
+                    if !paths.contains(&type_path) {
+                        paths.push(type_path);
+                    }
+                    id = composite.fields()[0].ty().id();
+                }
+                _ => break,
+            }
+        }
+    }
+    paths
+}
+
+/// Returns the paths of the types used in the keys of a `BTreeMap`, including the types of their
+/// fields. They derive `Ord`, which decoding a `BTreeMap` requires.
+fn btree_map_key_types(type_registry: &PortableRegistry) -> Vec<String> {
+    let mut paths = Vec::new();
+    let mut visited = HashSet::new();
+    let mut ids = type_registry
+        .enumerate()
+        .filter(|(_, ty)| {
+            ty.path().namespace().is_empty() && ty.path().ident() == Some("BTreeMap".to_string())
+        })
+        .filter_map(|(_, ty)| ty.type_params().first().map(|key| key.id()))
+        .collect::<Vec<_>>();
+    while let Some(id) = ids.pop() {
+        if !visited.insert(id) {
+            continue;
+        }
+        let ty = match type_registry.resolve(id) {
+            Some(ty) => ty,
+            None => continue,
+        };
+        if !ty.path().namespace().is_empty() {
+            let type_path = ty.path().segments().join("::");
+            if !paths.contains(&type_path) {
+                paths.push(type_path);
+            }
+        }
+        ids.extend(ty.type_params().iter().map(|param| param.id()));
+        match ty.type_def() {
+            TypeDef::Composite(composite) => {
+                ids.extend(composite.fields().iter().map(|field| field.ty().id()))
+            }
+            TypeDef::Variant(variant) => ids.extend(
+                variant
+                    .variants()
+                    .iter()
+                    .flat_map(|variant| variant.fields())
+                    .map(|field| field.ty().id()),
+            ),
+            TypeDef::Array(array) => ids.push(array.type_param().id()),
+            TypeDef::Sequence(sequence) => ids.push(sequence.type_param().id()),
+            TypeDef::Tuple(tuple) => ids.extend(tuple.fields().iter().map(|field| field.id())),
+            TypeDef::Compact(compact) => ids.push(compact.type_param().id()),
+            _ => (),
+        }
+    }
+    paths
+}
+
+/// Generate a self-contained 256-bit integer type, stored as little endian bytes so that it has
+/// the same SCALE encoding as the primitive.
+fn int_256_type(name: &str, signed: bool, derives: &TokenStream2) -> TokenStream2 {
+    let ident = format_ident!("{}", name);
+    let (int_128, sign_extend) = if signed {
+        (
+            quote! { i128 },
+            quote! {
+                if value < 0 {
+                    bytes[16..].copy_from_slice(&[0xff; 16]);
+                }
+            },
+        )
+    } else {
+        (quote! { u128 }, quote! {})
+    };
+    let docs = if signed {
+        " A 256-bit signed integer, stored as 32 little endian bytes."
+    } else {
+        " A 256-bit unsigned integer, stored as 32 little endian bytes."
+    };
+    quote! {
+        #[doc = #docs]
+        #derives
+        pub struct #ident(pub [u8; 32]);
+
+        impl #ident {
+            /// Create the integer from its little endian byte representation.
+            pub fn from_le_bytes(bytes: [u8; 32]) -> Self {
+                Self(bytes)
+            }
+
+            /// Returns the little endian byte representation of the integer.
+            pub fn to_le_bytes(&self) -> [u8; 32] {
+                self.0
+            }
+        }
+
+        impl From<#int_128> for #ident {
+            fn from(value: #int_128) -> Self {
+                let mut bytes = [0u8; 32];
+                bytes[..16].copy_from_slice(&value.to_le_bytes());
+                #sign_extend
+                Self(bytes)
+            }
+        }
+
+        impl From<[u8; 32]> for #ident {
+            fn from(bytes: [u8; 32]) -> Self {
+                Self(bytes)
+            }
+        }
+
+        impl From<#ident> for [u8; 32] {
+            fn from(value: #ident) -> Self {
+                value.0
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -572,7 +1037,7 @@ mod tests {
         let portable_types: PortableRegistry = registry.into();
 
         let type_gen = TypeGenerator::new(&portable_types, "root");
-        let types = type_gen.generate_types_mod();
+        let types = type_gen.generate_types_mod().unwrap();
         let tests_mod = types.get_mod(MOD_PATH).unwrap();
 
         assert_eq!(
@@ -613,7 +1078,7 @@ mod tests {
         let portable_types: PortableRegistry = registry.into();
 
         let type_gen = TypeGenerator::new(&portable_types, "root");
-        let types = type_gen.generate_types_mod();
+        let types = type_gen.generate_types_mod().unwrap();
         let tests_mod = types.get_mod(MOD_PATH).unwrap();
 
         assert_eq!(
@@ -653,7 +1118,7 @@ mod tests {
         let portable_types: PortableRegistry = registry.into();
 
         let type_gen = TypeGenerator::new(&portable_types, "root");
-        let types = type_gen.generate_types_mod();
+        let types = type_gen.generate_types_mod().unwrap();
         let tests_mod = types.get_mod(MOD_PATH).unwrap();
 
         assert_eq!(
@@ -688,7 +1153,7 @@ mod tests {
         let portable_types: PortableRegistry = registry.into();
 
         let type_gen = TypeGenerator::new(&portable_types, "root");
-        let types = type_gen.generate_types_mod();
+        let types = type_gen.generate_types_mod().unwrap();
         let tests_mod = types.get_mod(MOD_PATH).unwrap();
 
         assert_eq!(
@@ -721,7 +1186,7 @@ mod tests {
         let portable_types: PortableRegistry = registry.into();
 
         let type_gen = TypeGenerator::new(&portable_types, "root");
-        let types = type_gen.generate_types_mod();
+        let types = type_gen.generate_types_mod().unwrap();
         let tests_mod = types.get_mod(MOD_PATH).unwrap();
 
         assert_eq!(
@@ -753,7 +1218,7 @@ mod tests {
         let portable_types: PortableRegistry = registry.into();
 
         let type_gen = TypeGenerator::new(&portable_types, "root");
-        let types = type_gen.generate_types_mod();
+        let types = type_gen.generate_types_mod().unwrap();
         let tests_mod = types.get_mod(MOD_PATH).unwrap();
 
         assert_eq!(
@@ -790,7 +1255,7 @@ mod tests {
         let portable_types: PortableRegistry = registry.into();
 
         let type_gen = TypeGenerator::new(&portable_types, "root");
-        let types = type_gen.generate_types_mod();
+        let types = type_gen.generate_types_mod().unwrap();
         let tests_mod = types.get_mod(MOD_PATH).unwrap();
 
         assert_eq!(
@@ -809,6 +1274,368 @@ mod tests {
         )
     }
 
+    #[test]
+    fn compact_fields() {
+        #[allow(unused)]
+        #[derive(TypeInfo)]
+        struct S {
+            a: scale::Compact<u128>,
+            b: Vec<scale::Compact<u32>>,
+        }
+
+        #[allow(unused)]
+        #[derive(TypeInfo)]
+        enum E {
+            A(scale::Compact<u32>),
+            B { a: scale::Compact<u64> },
+        }
+
+        let mut registry = Registry::new();
+        registry.register_type(&meta_type::<S>());
+        registry.register_type(&meta_type::<E>());
+        let portable_types: PortableRegistry = registry.into();
+
+        let type_gen = TypeGenerator::new(&portable_types, "root");
+        let types = type_gen.generate_types_mod().unwrap();
+        let tests_mod = types.get_mod(MOD_PATH).unwrap();
+
+        assert_eq!(
+            tests_mod.into_token_stream().to_string(),
+            quote! {
+                pub mod tests {
+                    use super::root;
+                    #[derive(Debug, ::codec::Encode, ::codec::Decode)]
+                    pub enum E {
+                        A (#[codec(compact)] u32,),
+                        B { #[codec(compact)] a: u64, },
+                    }
+                    #[derive(Debug, ::codec::Encode, ::codec::Decode)]
+                    pub struct S {
+                        #[codec(compact)]
+                        pub a: u128,
+                        pub b: Vec<::codec::Compact<u32> >,
+                    }
+                }
+            }
+            .to_string()
+        )
+    }
+
+    #[test]
+    fn compact_structs_derive_compact_as() {
+        #[allow(unused)]
+        #[derive(TypeInfo)]
+        struct Perbill(u32);
+
+        #[allow(unused)]
+        #[derive(TypeInfo)]
+        struct S {
+            a: scale::Compact<Perbill>,
+            b: Perbill,
+        }
+
+        let mut registry = Registry::new();
+        registry.register_type(&meta_type::<S>());
+        let portable_types: PortableRegistry = registry.into();
+
+        let type_gen = TypeGenerator::new(&portable_types, "root");
+        let types = type_gen.generate_types_mod().unwrap();
+        let tests_mod = types.get_mod(MOD_PATH).unwrap();
+
+        assert_eq!(
+            tests_mod.into_token_stream().to_string(),
+            quote! {
+                pub mod tests {
+                    use super::root;
+                    #[derive(Debug, ::codec::Encode, ::codec::Decode, ::codec::CompactAs)]
+                    pub struct Perbill(pub u32,);
+                    #[derive(Debug, ::codec::Encode, ::codec::Decode)]
+                    pub struct S {
+                        #[codec(compact)]
+                        pub a: root::chameleon_core::generate_types::tests::Perbill,
+                        pub b: root::chameleon_core::generate_types::tests::Perbill,
+                    }
+                }
+            }
+            .to_string()
+        )
+    }
+
+    #[test]
+    fn btree_map_keys_derive_ord() {
+        #[allow(unused)]
+        #[derive(TypeInfo)]
+        struct Key {
+            a: Inner,
+        }
+
+        #[allow(unused)]
+        #[derive(TypeInfo)]
+        struct Inner(u8);
+
+        #[allow(unused)]
+        #[derive(TypeInfo)]
+        struct S {
+            a: std::collections::BTreeMap<Key, u32>,
+        }
+
+        let mut registry = Registry::new();
+        registry.register_type(&meta_type::<S>());
+        let portable_types: PortableRegistry = registry.into();
+
+        let type_gen = TypeGenerator::new(&portable_types, "root");
+        let types = type_gen.generate_types_mod().unwrap();
+        let tests_mod = types.get_mod(MOD_PATH).unwrap();
+
+        assert_eq!(
+            tests_mod.into_token_stream().to_string(),
+            quote! {
+                pub mod tests {
+                    use super::root;
+                    #[derive(Debug, ::codec::Encode, ::codec::Decode, PartialEq, Eq, PartialOrd, Ord)]
+                    pub struct Inner(pub u8,);
+                    #[derive(Debug, ::codec::Encode, ::codec::Decode, PartialEq, Eq, PartialOrd, Ord)]
+                    pub struct Key {
+                        pub a: root::chameleon_core::generate_types::tests::Inner,
+                    }
+                    #[derive(Debug, ::codec::Encode, ::codec::Decode)]
+                    pub struct S {
+                        pub a: std::collections::BTreeMap<root::chameleon_core::generate_types::tests::Key, u32>,
+                    }
+                }
+            }
+            .to_string()
+        )
+    }
+
+    #[test]
+    fn int_256_primitives() {
+        struct U256;
+
+        impl TypeInfo for U256 {
+            type Identity = Self;
+
+            fn type_info() -> Type {
+                TypeDefPrimitive::U256.into()
+            }
+        }
+
+        #[allow(unused)]
+        #[derive(TypeInfo)]
+        struct S {
+            a: U256,
+        }
+
+        let mut registry = Registry::new();
+        registry.register_type(&meta_type::<S>());
+        let portable_types: PortableRegistry = registry.into();
+
+        let type_gen = TypeGenerator::new(&portable_types, "root");
+        let types = type_gen.generate_types_mod().unwrap();
+        let tests_mod = types.get_mod(MOD_PATH).unwrap();
+
+        assert_eq!(
+            tests_mod.into_token_stream().to_string(),
+            quote! {
+                pub mod tests {
+                    use super::root;
+                    #[derive(Debug, ::codec::Encode, ::codec::Decode)]
+                    pub struct S {
+                        pub a: root::U256,
+                    }
+                }
+            }
+            .to_string()
+        );
+        assert!(types
+            .into_token_stream()
+            .to_string()
+            .contains(&int_256_type("U256", false, &type_gen.derives()).to_string()));
+
+        let config = TypeGeneratorConfig {
+            primitive_types: true,
+            ..Default::default()
+        };
+        let type_gen = TypeGenerator::with_config(&portable_types, "root", config);
+        let types = type_gen.generate_types_mod().unwrap();
+        let tests_mod = types.get_mod(MOD_PATH).unwrap();
+
+        assert_eq!(
+            tests_mod.into_token_stream().to_string(),
+            quote! {
+                pub mod tests {
+                    use super::root;
+                    #[derive(Debug, ::codec::Encode, ::codec::Decode)]
+                    pub struct S {
+                        pub a: ::primitive_types::U256,
+                    }
+                }
+            }
+            .to_string()
+        );
+        assert!(!types
+            .into_token_stream()
+            .to_string()
+            .contains("pub struct U256"));
+    }
+
+    #[test]
+    fn int_256_type_derives() {
+        struct I256;
+
+        impl TypeInfo for I256 {
+            type Identity = Self;
+
+            fn type_info() -> Type {
+                TypeDefPrimitive::I256.into()
+            }
+        }
+
+        let mut registry = Registry::new();
+        registry.register_type(&meta_type::<I256>());
+        let portable_types: PortableRegistry = registry.into();
+
+        let config =
+            TypeGeneratorConfig::default().derive_for_type("I256", syn::parse_quote!(Hash));
+        let type_gen = TypeGenerator::with_config(&portable_types, "root", config);
+        let types = type_gen.generate_types_mod().unwrap();
+
+        let derives = quote! { #[derive(Debug, ::codec::Encode, ::codec::Decode, Hash)] };
+        assert!(types
+            .into_token_stream()
+            .to_string()
+            .contains(&int_256_type("I256", true, &derives).to_string()));
+    }
+
+    #[test]
+    fn docs() {
+        /// A struct.
+        #[allow(unused)]
+        #[derive(TypeInfo)]
+        struct S {
+            /// A field.
+            a: bool,
+        }
+
+        /// An enum.
+        #[allow(unused)]
+        #[derive(TypeInfo)]
+        enum E {
+            /// A variant.
+            A(u32),
+        }
+
+        let mut registry = Registry::new();
+        registry.register_type(&meta_type::<S>());
+        registry.register_type(&meta_type::<E>());
+        let portable_types: PortableRegistry = registry.into();
+
+        let type_gen = TypeGenerator::new(&portable_types, "root");
+        let types = type_gen.generate_types_mod().unwrap();
+        let tests_mod = types.get_mod(MOD_PATH).unwrap();
+
+        assert_eq!(
+            tests_mod.into_token_stream().to_string(),
+            quote! {
+                pub mod tests {
+                    use super::root;
+                    #[doc = " An enum."]
+                    #[derive(Debug, ::codec::Encode, ::codec::Decode)]
+                    pub enum E {
+                        #[doc = " A variant."]
+                        A(u32),
+                    }
+                    #[doc = " A struct."]
+                    #[derive(Debug, ::codec::Encode, ::codec::Decode)]
+                    pub struct S {
+                        #[doc = " A field."]
+                        pub a: bool,
+                    }
+                }
+            }
+            .to_string()
+        )
+    }
+
+    #[test]
+    fn derives() {
+        #[allow(unused)]
+        #[derive(TypeInfo)]
+        struct A(u32);
+
+        #[allow(unused)]
+        #[derive(TypeInfo)]
+        struct B(bool);
+
+        let mut registry = Registry::new();
+        registry.register_type(&meta_type::<A>());
+        registry.register_type(&meta_type::<B>());
+        let portable_types: PortableRegistry = registry.into();
+
+        let config = TypeGeneratorConfig::default()
+            .derive(syn::parse_quote!(Clone))
+            .derive_for_type(
+                "chameleon_core::generate_types::tests::B",
+                syn::parse_quote!(Hash),
+            );
+        let type_gen = TypeGenerator::with_config(&portable_types, "root", config);
+        let types = type_gen.generate_types_mod().unwrap();
+        let tests_mod = types.get_mod(MOD_PATH).unwrap();
+
+        assert_eq!(
+            tests_mod.into_token_stream().to_string(),
+            quote! {
+                pub mod tests {
+                    use super::root;
+                    #[derive(Debug, ::codec::Encode, ::codec::Decode, Clone)]
+                    pub struct A(pub u32,);
+                    #[derive(Debug, ::codec::Encode, ::codec::Decode, Clone, Hash)]
+                    pub struct B(pub bool,);
+                }
+            }
+            .to_string()
+        )
+    }
+
+    #[test]
+    fn type_substitutes() {
+        #[allow(unused)]
+        #[derive(TypeInfo)]
+        struct Child<T>(T);
+
+        #[allow(unused)]
+        #[derive(TypeInfo)]
+        struct Parent {
+            a: Child<bool>,
+        }
+
+        let mut registry = Registry::new();
+        registry.register_type(&meta_type::<Parent>());
+        let portable_types: PortableRegistry = registry.into();
+
+        let config = TypeGeneratorConfig::default().substitute_type(
+            "chameleon_core::generate_types::tests::Child",
+            syn::parse_quote!(::my_types::Child),
+        );
+        let type_gen = TypeGenerator::with_config(&portable_types, "root", config);
+        let types = type_gen.generate_types_mod().unwrap();
+        let tests_mod = types.get_mod(MOD_PATH).unwrap();
+
+        assert_eq!(
+            tests_mod.into_token_stream().to_string(),
+            quote! {
+                pub mod tests {
+                    use super::root;
+                    #[derive(Debug, ::codec::Encode, ::codec::Decode)]
+                    pub struct Parent {
+                        pub a: ::my_types::Child<bool>,
+                    }
+                }
+            }
+            .to_string()
+        )
+    }
+
     #[test]
     fn generics() {
         #[allow(unused)]
@@ -829,7 +1656,7 @@ mod tests {
         let portable_types: PortableRegistry = registry.into();
 
         let type_gen = TypeGenerator::new(&portable_types, "root");
-        let types = type_gen.generate_types_mod();
+        let types = type_gen.generate_types_mod().unwrap();
         let tests_mod = types.get_mod(MOD_PATH).unwrap();
 
         assert_eq!(
@@ -872,7 +1699,7 @@ mod tests {
         let portable_types: PortableRegistry = registry.into();
 
         let type_gen = TypeGenerator::new(&portable_types, "root");
-        let types = type_gen.generate_types_mod();
+        let types = type_gen.generate_types_mod().unwrap();
         let tests_mod = types.get_mod(MOD_PATH).unwrap();
 
         assert_eq!(
@@ -925,7 +1752,7 @@ mod tests {
         let portable_types: PortableRegistry = registry.into();
 
         let type_gen = TypeGenerator::new(&portable_types, "root");
-        let types = type_gen.generate_types_mod();
+        let types = type_gen.generate_types_mod().unwrap();
         let tests_mod = types.get_mod(MOD_PATH).unwrap();
 
         assert_eq!(
@@ -980,7 +1807,7 @@ mod tests {
         let portable_types: PortableRegistry = registry.into();
 
         let type_gen = TypeGenerator::new(&portable_types, "root");
-        let types = type_gen.generate_types_mod();
+        let types = type_gen.generate_types_mod().unwrap();
         let tests_mod = types.get_mod(MOD_PATH).unwrap();
 
         assert_eq!(
@@ -1020,4 +1847,21 @@ mod tests {
             .to_string()
         )
     }
+
+    #[test]
+    fn variant_indices() {
+        use scale::{Decode as _, Encode as _};
+
+        let variant = |discriminant: Option<u64>| {
+            let encoded = ("A", Vec::<()>::new(), discriminant, Vec::<String>::new()).encode();
+            Variant::<PortableForm>::decode(&mut &encoded[..]).unwrap()
+        };
+
+        assert_eq!(variant_index(3, &variant(None)).unwrap(), 3);
+        assert_eq!(variant_index(3, &variant(Some(255))).unwrap(), 255);
+        assert!(matches!(
+            variant_index(3, &variant(Some(256))),
+            Err(Error::InvalidVariantIndex { index: 256, .. })
+        ));
+    }
 }
@@ -1,15 +1,33 @@
+use frame_metadata::{v13::RuntimeMetadataV13, RuntimeMetadata, RuntimeMetadataPrefixed};
 use proc_macro2::TokenStream as TokenStream2;
 use scale::Decode;
 use std::{
+    borrow::Cow,
     fs,
     io::{self, Read},
     path,
 };
 
+pub mod compat;
+pub mod diff;
+pub mod dynamic;
+pub mod events;
+mod generate_events;
+mod generate_extrinsic;
+mod generate_legacy;
 mod generate_runtime;
+mod generate_runtime_api;
+mod generate_storage;
 mod generate_types;
+#[cfg(feature = "signing")]
+pub mod signing;
+#[cfg(test)]
+mod test_utils;
+mod v14;
 
-pub use generate_types::TypeGenerator;
+pub use generate_legacy::LegacyTypeRegistry;
+pub use generate_runtime_api::{RuntimeApiMethod, RuntimeApis};
+pub use generate_types::{TypeGenerator, TypeGeneratorConfig};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -17,22 +35,200 @@ pub enum Error {
     Codec(#[from] scale::Error),
     #[error("Io error")]
     Io(#[from] io::Error),
+    #[error("Error parsing generated code")]
+    Syntax(#[from] syn::Error),
+    #[error("Unsupported metadata version V{0}, only V12, V13 and V14 are supported")]
+    UnsupportedMetadataVersion(u8),
+    #[error("No type with id {id} found in the type registry")]
+    TypeNotFound { id: u32 },
+    #[error("Fields of type `{type_path}` must be either all named or all unnamed")]
+    MixedFieldNames { type_path: String },
+    #[error("Expected type with id {id} to be a variant type")]
+    ExpectedVariantType { id: u32 },
+    #[error("Variant `{variant}` has the index {index}, which doesn't fit in a `u8`")]
+    InvalidVariantIndex { variant: String, index: u64 },
+    #[error("Invalid legacy type name `{name}`")]
+    InvalidLegacyTypeName { name: String },
+    #[error("No Rust type registered for the legacy type name `{name}`")]
+    UnknownLegacyType { name: String },
+    #[error("Expected `<type name> = <rust type>` on line {line} of the legacy type registry")]
+    InvalidLegacyTypeRegistry { line: usize },
+    #[error("Invalid hex encoded runtime metadata")]
+    Hex(#[from] hex::FromHexError),
+    #[error("Invalid JSON-RPC response")]
+    Json(#[from] serde_json::Error),
+    #[error("Expected a JSON-RPC response with the metadata as a hex string: {0}")]
+    InvalidJsonRpcResponse(String),
+    #[error("Error decoding V14 runtime metadata")]
+    CodecV14(#[from] scale_v3::Error),
+    #[error("V{version} metadata has no type registry, only V13 and V14 metadata are supported")]
+    MissingTypeRegistry { version: u8 },
+    #[error("Unsupported type with id {id} for dynamic values: {reason}")]
+    UnsupportedDynamicType { id: u32, reason: &'static str },
+    #[error("No variant `{variant}` found in the type with id {id}")]
+    UnknownVariant { id: u32, variant: String },
+    #[error("Value doesn't match the type with id {id}, expected {expected}")]
+    ValueMismatch { id: u32, expected: &'static str },
+    #[error("No `System::Events` storage entry holding event records found in the metadata")]
+    MissingEventRecords,
+    #[error(
+        "Expected `<Api>_<method>(<arg>: <type id>, ..) -> <type id>` on line {line} of the \
+         runtime API declarations"
+    )]
+    InvalidRuntimeApiDeclaration { line: usize },
+    #[error("The runtime API method `{api}_{method}` is declared more than once")]
+    DuplicateRuntimeApiMethod { api: String, method: String },
+    #[error(
+        "No type with id {id} found in the metadata, used by the runtime API method `{method}`"
+    )]
+    UnknownRuntimeApiType { method: String, id: u32 },
+    #[error(
+        "Unknown type of the additional signed data of the `{identifier}` signed extension, it \
+         can be configured with `additional_signed_type`"
+    )]
+    UnknownAdditionalSigned { identifier: String },
+    #[cfg(feature = "signing")]
+    #[error("Invalid {scheme} secret key")]
+    InvalidSecretKey { scheme: &'static str },
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-pub fn generate_runtime_types<P>(mod_name: &str, path: P) -> Result<TokenStream2>
+pub fn generate_runtime_types<P>(
+    mod_name: &str,
+    path: P,
+    config: TypeGeneratorConfig,
+) -> Result<TokenStream2>
 where
     P: AsRef<path::Path>,
 {
-    let mut file = fs::File::open(path).expect("Error opening file");
+    let mut file = fs::File::open(path)?;
     let mut bytes = Vec::new();
     file.read_to_end(&mut bytes)?;
 
-    let metadata = frame_metadata::RuntimeMetadataPrefixed::decode(&mut &bytes[..])?;
+    generate_runtime_types_from_bytes(mod_name, &bytes, config)
+}
+
+/// Generate the runtime module from SCALE encoded runtime metadata, e.g. as read from stdin.
+///
+/// The metadata can be SCALE encoded bytes, `0x` prefixed hex text, or the JSON-RPC response to a
+/// `state_getMetadata` request, as returned by a node.
+///
+/// V13 and V14 metadata are supported. Legacy V12 metadata is supported by mapping its type names
+/// onto Rust types with the [`TypeGeneratorConfig::legacy_types`] registry. The options which need a
+/// type registry, such as the compatibility check, are rejected with
+/// [`Error::MissingTypeRegistry`] for V12 metadata.
+pub fn generate_runtime_types_from_bytes(
+    mod_name: &str,
+    bytes: &[u8],
+    config: TypeGeneratorConfig,
+) -> Result<TokenStream2> {
+    let (RuntimeMetadataPrefixed(prefix, metadata), type_id_offset) = decode_metadata(bytes)?;
+
+    match metadata {
+        RuntimeMetadata::V12(_)
+            if config.compatibility_check
+                || config.tolerant_events
+                || !config.runtime_apis.is_empty()
+                || !config.additional_signed_types.is_empty() =>
+        {
+            Err(Error::MissingTypeRegistry { version: 12 })
+        }
+        RuntimeMetadata::V12(metadata) => {
+            let generator = generate_legacy::LegacyRuntimeGenerator::new(metadata);
+            generator.generate_runtime(mod_name, &config)
+        }
+        metadata => {
+            let generator = generate_runtime::RuntimeGenerator::new(
+                RuntimeMetadataPrefixed(prefix, metadata),
+                type_id_offset,
+            )?;
+            generator.generate_runtime(mod_name, config)
+        }
+    }
+}
+
+/// Decode the metadata in any of the formats accepted by [`generate_runtime_types_from_bytes`],
+/// converting V14 metadata into the V13 representation.
+///
+/// Also returns the offset to add to the type ids of the metadata to get the ids of the decoded
+/// type registry, which is 1 for V14 metadata since its ids are 0 based.
+fn decode_metadata(bytes: &[u8]) -> Result<(RuntimeMetadataPrefixed, u32)> {
+    let bytes = &*decode_metadata_text(bytes)?;
+
+    // V14 metadata is decoded with the released `frame-metadata`, which doesn't support the
+    // prototype V13 format, so check the version following the `meta` magic number first
+    if bytes.get(4) == Some(&14) {
+        let frame_metadata_v14::RuntimeMetadataPrefixed(prefix, metadata) =
+            scale_v3::Decode::decode(&mut &bytes[..])?;
+        if let frame_metadata_v14::RuntimeMetadata::V14(metadata) = metadata {
+            let metadata = RuntimeMetadata::V13(v14::into_v13(&metadata)?);
+            return Ok((RuntimeMetadataPrefixed(prefix, metadata), 1));
+        }
+    }
+
+    Ok((RuntimeMetadataPrefixed::decode(&mut &bytes[..])?, 0))
+}
+
+/// Decode V13 or V14 metadata, which have a type registry, as V13 metadata.
+fn decode_metadata_v13(bytes: &[u8]) -> Result<RuntimeMetadataV13> {
+    match (decode_metadata(bytes)?.0).1 {
+        RuntimeMetadata::V13(metadata) => Ok(metadata),
+        RuntimeMetadata::V12(_) => Err(Error::MissingTypeRegistry { version: 12 }),
+        other => {
+            // the index of the encoded enum variant is the metadata version
+            let version = scale::Encode::using_encoded(&other, |bytes| bytes[0]);
+            Err(Error::UnsupportedMetadataVersion(version))
+        }
+    }
+}
+
+/// Returns the SCALE encoded metadata from `0x` prefixed hex text or a JSON-RPC response, or the
+/// bytes unchanged if they are already SCALE encoded.
+fn decode_metadata_text(bytes: &[u8]) -> Result<Cow<'_, [u8]>> {
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) if !bytes.starts_with(b"meta") => text.trim(),
+        _ => return Ok(Cow::Borrowed(bytes)),
+    };
 
-    let generator = generate_runtime::RuntimeGenerator::new(metadata);
-    Ok(generator.generate_runtime(mod_name))
+    let hex_text = if text.starts_with('{') {
+        let response: serde_json::Value = serde_json::from_str(text)?;
+        match response.get("result").and_then(|result| result.as_str()) {
+            Some(result) => result.to_string(),
+            None => {
+                let error = response
+                    .get("error")
+                    .map_or_else(|| "missing result".to_string(), |error| error.to_string());
+                return Err(Error::InvalidJsonRpcResponse(error));
+            }
+        }
+    } else if text.starts_with("0x") {
+        text.to_string()
+    } else {
+        return Ok(Cow::Borrowed(bytes));
+    };
+
+    let hex_text = hex_text.trim_start_matches("0x");
+    Ok(Cow::Owned(hex::decode(hex_text)?))
+}
+
+/// Generate the runtime module and pretty print it as Rust source code.
+pub fn generate_runtime_source<P>(
+    mod_name: &str,
+    path: P,
+    config: TypeGeneratorConfig,
+) -> Result<String>
+where
+    P: AsRef<path::Path>,
+{
+    let tokens = generate_runtime_types(mod_name, path, config)?;
+    format_source(tokens)
+}
+
+/// Pretty print generated code, so it can be written to a file without requiring `rustfmt`.
+pub fn format_source(tokens: TokenStream2) -> Result<String> {
+    let file = syn::parse2::<syn::File>(tokens)?;
+    Ok(prettyplease::unparse(&file))
 }
 
 #[cfg(test)]
@@ -44,15 +240,349 @@ mod tests {
         let root = env::var("CARGO_MANIFEST_DIR").unwrap_or(".".into());
         let root_path = path::Path::new(&root);
 
-        // generate with:
+        // the JSON-RPC response can also be saved directly, e.g.:
         // curl -sX POST -H "Content-Type: application/json" --data '{"jsonrpc":"2.0","method":"state_getMetadata", "id": 1}' localhost:9933 \
-        // | jq .result \
-        // | cut -d '"' -f 2 \
-        // | xxd -r -p > ./core/node-runtime.scale
-        //
-        //
+        // > ./core/node-runtime.json
         let path = root_path.join("node-runtime.scale");
 
-        super::generate_runtime_types("test_runtime", path).unwrap();
+        super::generate_runtime_types("test_runtime", path, Default::default()).unwrap();
+    }
+
+    #[test]
+    fn missing_metadata_file_is_an_error() {
+        let result = super::generate_runtime_types(
+            "test_runtime",
+            "does-not-exist.scale",
+            Default::default(),
+        );
+
+        assert!(matches!(result, Err(super::Error::Io(_))));
+    }
+
+    fn node_runtime_source() -> String {
+        let root = env::var("CARGO_MANIFEST_DIR").unwrap_or(".".into());
+        let path = path::Path::new(&root).join("node-runtime.scale");
+
+        super::generate_runtime_source("test_runtime", path, Default::default()).unwrap()
+    }
+
+    #[test]
+    fn generate_runtime_source() {
+        let source = node_runtime_source();
+
+        assert!(source.starts_with(
+            "#[allow(dead_code, unused_imports, non_camel_case_types)]\npub mod test_runtime {\n"
+        ));
+        assert!(source.contains("\n    pub mod balances {\n"));
+    }
+
+    #[test]
+    fn generate_storage_entries() {
+        let source = node_runtime_source();
+
+        assert!(source
+            .contains("\n        pub mod storage {\n            use super::__runtime_types;\n"));
+        assert!(source.contains(
+            "\n            /// The full account information for a particular account ID.\n            pub struct Account(pub __runtime_types::sp_core::crypto::AccountId32);\n"
+        ));
+        assert!(source.contains("const PALLET: &'static str = \"Balances\";"));
+        assert!(source.contains("super::super::StorageHasher::Blake2_128Concat"));
+    }
+
+    #[test]
+    fn generate_constants() {
+        let source = node_runtime_source();
+
+        assert!(source.contains(
+            "\n            pub fn existential_deposit() -> Result<u128, ::codec::Error> {\n"
+        ));
+    }
+
+    #[test]
+    fn generate_errors() {
+        let source = node_runtime_source();
+
+        assert!(source.contains("\n    pub enum RuntimeError {\n"));
+        assert!(source.contains("Balances(balances::Error),"));
+        assert!(source.contains("\n        pub enum Error {\n"));
+        assert!(!source.contains("__Ignore =>"));
+    }
+
+    #[test]
+    fn generate_errors_with_indices_and_fields() {
+        let root = env::var("CARGO_MANIFEST_DIR").unwrap_or(".".into());
+        let path = path::Path::new(&root).join("test-runtime-v14-indices.scale");
+        let source =
+            super::generate_runtime_source("test_runtime", path, Default::default()).unwrap();
+
+        assert!(source.contains("#[codec(index = 4)]\n            TooLong { max: u32 },"));
+        assert!(source.contains("#[codec(index = 6)]\n            Invalid(u32),"));
+        assert!(source.contains("Self::TooLong { .. } => \"TooLong\","));
+        // the `Empty` pallet has no errors, so it has no `Error` enum or `RuntimeError` variant
+        assert!(source.contains("pub mod empty {\n        use super::__runtime_types;\n    }"));
+        assert!(!source.contains("Empty(empty::Error)"));
+    }
+
+    #[test]
+    fn generate_calls() {
+        let source = node_runtime_source();
+
+        assert!(source.contains("\n    pub enum Call {\n"));
+        assert!(source.contains("Balances(balances::Call),"));
+        assert!(source.contains("Transfer(calls::Transfer),"));
+        assert!(source.contains("\n        pub mod calls {\n"));
+        assert!(source.contains("pub fn transfer("));
+        assert!(source.contains("super::Call::Transfer(Transfer { dest, value })"));
+    }
+
+    #[test]
+    fn generate_extrinsic() {
+        let source = node_runtime_source();
+
+        assert!(source.contains("\n    pub mod extrinsic {\n"));
+        assert!(source.contains("pub const VERSION: u8 = 4;"));
+        assert!(source.contains("\n        pub struct SignedExtra {\n"));
+        assert!(source.contains(
+            "pub check_nonce: __runtime_types::frame_system::extensions::check_nonce::CheckNonce<"
+        ));
+        assert!(source.contains("pub charge_transaction_payment: "));
+        assert!(source.contains("\n        pub struct AdditionalSigned {\n"));
+        assert!(source.contains("pub check_genesis: __runtime_types::primitive_types::H256,"));
+        assert!(!source.contains("pub check_nonce: u32"));
+        assert!(source.contains("pub fn signer_payload("));
+        assert!(source.contains("pub struct UncheckedExtrinsic<Address, Signature> {"));
+    }
+
+    /// Generate the node runtime, with an unknown `CheckFoo` signed extension.
+    fn generate_with_unknown_signed_extension(
+        config: super::TypeGeneratorConfig,
+    ) -> super::Result<String> {
+        use frame_metadata::{v13::SignedExtensionMetadata, RuntimeMetadata};
+        use scale::Decode as _;
+
+        let bytes = crate::test_utils::read_metadata("node-runtime.scale");
+        let mut metadata = frame_metadata::RuntimeMetadataPrefixed::decode(&mut &bytes[..])?;
+        if let RuntimeMetadata::V13(ref mut metadata) = metadata.1 {
+            let ty = metadata.extrinsic.signed_extensions[0].ty;
+            metadata
+                .extrinsic
+                .signed_extensions
+                .push(SignedExtensionMetadata {
+                    identifier: "CheckFoo".into(),
+                    ty,
+                });
+        }
+        let generator = super::generate_runtime::RuntimeGenerator::new(metadata, 0)?;
+        super::format_source(generator.generate_runtime("test_runtime", config)?)
+    }
+
+    #[test]
+    fn unknown_additional_signed_is_an_error() {
+        let result = generate_with_unknown_signed_extension(Default::default());
+
+        assert!(matches!(
+            result,
+            Err(super::Error::UnknownAdditionalSigned { ref identifier }) if identifier == "CheckFoo"
+        ));
+    }
+
+    #[test]
+    fn configured_additional_signed_type() {
+        let config = super::TypeGeneratorConfig::default()
+            .additional_signed_type("CheckFoo", syn::parse_quote!((u32, u64)));
+
+        let source = generate_with_unknown_signed_extension(config).unwrap();
+
+        assert!(source.contains("pub check_foo: (u32, u64),"));
+    }
+
+    #[test]
+    fn generate_event_records() {
+        let source = node_runtime_source();
+
+        assert!(source.contains("pub struct EventRecords(pub Vec<EventRecord>);"));
+        assert!(source.contains("pub event: Event,"));
+        assert!(source.contains("pub fn find_first<E: PalletEvent>(&self) -> Option<&E> {"));
+        assert!(source.contains("impl PalletEvent for balances::Event {"));
+    }
+
+    #[test]
+    fn generate_v14_runtime_source() {
+        let root = env::var("CARGO_MANIFEST_DIR").unwrap_or(".".into());
+        let path = path::Path::new(&root).join("test-runtime-v14.scale");
+        let source =
+            super::generate_runtime_source("test_runtime", path, Default::default()).unwrap();
+
+        assert!(source.contains("\n    pub mod balances {\n"));
+        assert!(source.contains("#[codec(index = 5)]\n        Balances(balances::Call),"));
+        assert!(source.contains("pub fn transfer_keep_alive("));
+        assert!(source.contains("pub struct EventTopics("));
+        assert!(source.contains("pub fn existential_deposit() -> Result<u128, ::codec::Error> {"));
+    }
+
+    #[test]
+    fn generate_v14_call_indices_and_bit_sequences() {
+        let root = env::var("CARGO_MANIFEST_DIR").unwrap_or(".".into());
+        let path = path::Path::new(&root).join("test-runtime-v14-indices.scale");
+        let source =
+            super::generate_runtime_source("test_runtime", path, Default::default()).unwrap();
+
+        assert!(source.contains(
+            "#[codec(index = 3)]\n            Store(calls::Store),\n            \
+             #[codec(index = 7)]\n            Clear(calls::Clear),"
+        ));
+        assert!(source.contains("pub bits: ::bitvec::vec::BitVec<::bitvec::order::Lsb0, u8>,"));
+    }
+
+    #[test]
+    fn generate_compatibility_check() {
+        let root = env::var("CARGO_MANIFEST_DIR").unwrap_or(".".into());
+        let path = path::Path::new(&root).join("test-runtime-v14.scale");
+        let config = super::TypeGeneratorConfig {
+            compatibility_check: true,
+            ..Default::default()
+        };
+
+        let source = super::generate_runtime_source("test_runtime", &path, config).unwrap();
+
+        assert!(source
+            .contains("pub static METADATA_HASHES: &[::chameleon_core::compat::PalletHashes]"));
+        assert!(source.contains("name: \"Balances\","));
+        assert!(source.contains("(\"transfer_keep_alive\", 0x"));
+        assert!(source.contains("(\"TotalIssuance\", 0x"));
+        assert!(source.contains("(\"Transfer\", 0x"));
+        assert!(source.contains(") -> Result<(), ::chameleon_core::compat::Incompatibilities> {"));
+        assert!(source.contains("pub fn changed_pallets("));
+        // the hashes are only embedded when configured
+        let source =
+            super::generate_runtime_source("test_runtime", path, Default::default()).unwrap();
+        assert!(!source.contains("check_compatibility"));
+    }
+
+    #[test]
+    fn generate_tolerant_event_decoding() {
+        let root = env::var("CARGO_MANIFEST_DIR").unwrap_or(".".into());
+        let path = path::Path::new(&root).join("test-runtime-v14.scale");
+        let config = super::TypeGeneratorConfig {
+            tolerant_events: true,
+            ..Default::default()
+        };
+
+        let source = super::generate_runtime_source("test_runtime", &path, config).unwrap();
+
+        assert!(source.contains("pub enum TolerantEvent {"));
+        assert!(source.contains("decoder: &::chameleon_core::events::EventRecordsDecoder,"));
+        let source =
+            super::generate_runtime_source("test_runtime", path, Default::default()).unwrap();
+        assert!(source.contains("pub struct EventRecords("));
+        assert!(!source.contains("decode_tolerant"));
+    }
+
+    #[test]
+    fn generate_runtime_api() {
+        let root = env::var("CARGO_MANIFEST_DIR").unwrap_or(".".into());
+        let path = path::Path::new(&root).join("test-runtime-v14.scale");
+        let config = super::TypeGeneratorConfig {
+            runtime_apis: super::RuntimeApis::parse(
+                "AccountNonceApi_account_nonce(account: 0) -> 4",
+            )
+            .unwrap(),
+            ..Default::default()
+        };
+
+        let source = super::generate_runtime_source("test_runtime", &path, config).unwrap();
+
+        assert!(source.contains("\n    pub mod runtime_api {\n"));
+        assert!(source.contains("\n        pub mod account_nonce_api {\n"));
+        assert!(source.contains(
+            "pub fn account_nonce(\n                account: __runtime_types::sp_core::crypto::AccountId32,\n            ) -> StateCall<u32> {"
+        ));
+        assert!(source.contains("StateCall::new(\"AccountNonceApi_account_nonce\", args)"));
+        // no module is generated without declarations, and the type ids must exist
+        let source =
+            super::generate_runtime_source("test_runtime", &path, Default::default()).unwrap();
+        assert!(!source.contains("pub mod runtime_api"));
+        let config = super::TypeGeneratorConfig {
+            runtime_apis: super::RuntimeApis::parse("Metadata_metadata() -> 1000").unwrap(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            super::generate_runtime_source("test_runtime", path, config),
+            Err(super::Error::UnknownRuntimeApiType { ref method, id: 1000 })
+                if method == "Metadata_metadata"
+        ));
+        // the ids of V13 metadata are 1 based, so they aren't shifted
+        let path = path::Path::new(&root).join("node-runtime.scale");
+        let config = super::TypeGeneratorConfig {
+            runtime_apis: super::RuntimeApis::parse("Metadata_metadata() -> 0").unwrap(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            super::generate_runtime_source("test_runtime", path, config),
+            Err(super::Error::UnknownRuntimeApiType { id: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn generate_legacy_runtime_source() {
+        let root = env::var("CARGO_MANIFEST_DIR").unwrap_or(".".into());
+        let root_path = path::Path::new(&root);
+        let legacy_types = std::fs::read_to_string(root_path.join("ksm-legacy-types.txt")).unwrap();
+        let config = super::TypeGeneratorConfig {
+            legacy_types: super::LegacyTypeRegistry::parse(&legacy_types).unwrap(),
+            ..Default::default()
+        }
+        .derive(syn::parse_quote!(Clone))
+        .derive_for_type("balances::calls::Transfer", syn::parse_quote!(Hash))
+        .substitute_type("Perbill", syn::parse_quote!(::sp_arithmetic::Perbill));
+        let path = root_path.join("ksm-metadata-v12.scale");
+
+        let source = super::generate_runtime_source("test_runtime", &path, config).unwrap();
+
+        assert!(source.contains("#[codec(index = 4)]\n        Balances(balances::Call),"));
+        assert!(source.contains(
+            "#[derive(Debug, ::codec::Encode, ::codec::Decode, Clone, Hash)]\n            pub struct Transfer {"
+        ));
+        assert!(source.contains(
+            "#[derive(Debug, ::codec::Encode, ::codec::Decode, Clone)]\n            pub struct TransferKeepAlive {"
+        ));
+        assert!(source.contains("Transfer([u8; 32], [u8; 32], u128),"));
+        assert!(source.contains("::sp_arithmetic::Perbill"));
+        // options which need a type registry are rejected
+        let config = super::TypeGeneratorConfig::default()
+            .additional_signed_type("CheckFoo", syn::parse_quote!(u32));
+        assert!(matches!(
+            super::generate_runtime_source("test_runtime", path, config),
+            Err(super::Error::MissingTypeRegistry { version: 12 })
+        ));
+    }
+
+    #[test]
+    fn metadata_as_hex_or_json_rpc_response() {
+        let root = env::var("CARGO_MANIFEST_DIR").unwrap_or(".".into());
+        let bytes = std::fs::read(path::Path::new(&root).join("node-runtime.scale")).unwrap();
+        let hex = format!("0x{}\n", hex::encode(&bytes));
+        let response = format!(r#"{{"jsonrpc":"2.0","result":"{}","id":1}}"#, hex.trim());
+
+        assert_eq!(super::decode_metadata_text(&bytes).unwrap(), &bytes[..]);
+        assert_eq!(
+            super::decode_metadata_text(hex.as_bytes()).unwrap(),
+            &bytes[..]
+        );
+        assert_eq!(
+            super::decode_metadata_text(response.as_bytes()).unwrap(),
+            &bytes[..]
+        );
+    }
+
+    #[test]
+    fn json_rpc_error_response_is_an_error() {
+        let response =
+            r#"{"jsonrpc":"2.0","error":{"code":-32601,"message":"Method not found"},"id":1}"#;
+
+        assert!(matches!(
+            super::decode_metadata_text(response.as_bytes()),
+            Err(super::Error::InvalidJsonRpcResponse(error)) if error.contains("Method not found")
+        ));
     }
 }
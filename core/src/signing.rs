@@ -0,0 +1,260 @@
+//! Signing the payloads of signed extrinsics with sr25519, ed25519 or ecdsa keys, available with
+//! the `signing` feature.
+//!
+//! The signatures are returned as a [`MultiSignature`], which is encoded like the runtime's
+//! `sp_runtime::MultiSignature`, so it can be used as the signature type of the generated
+//! `UncheckedExtrinsic`, or decoded as the generated `MultiSignature` type.
+
+use crate::{Error, Result};
+use scale::{Decode, Encode};
+
+/// The largest payload which is signed as is, longer payloads are signed as their `blake2_256`
+/// hash.
+pub const MAX_UNHASHED_PAYLOAD_LEN: usize = 256;
+
+/// A signature of one of the supported schemes, encoded like `sp_runtime::MultiSignature`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MultiSignature {
+    Ed25519([u8; 64]),
+    Sr25519([u8; 64]),
+    /// The signature followed by the recovery id.
+    Ecdsa([u8; 65]),
+}
+
+// implemented by hand since the codec derive can't tell the renamed codec versions of this crate
+// apart
+impl Encode for MultiSignature {
+    fn encode_to<T: scale::Output + ?Sized>(&self, dest: &mut T) {
+        match self {
+            Self::Ed25519(signature) => {
+                dest.push_byte(0);
+                dest.write(signature);
+            }
+            Self::Sr25519(signature) => {
+                dest.push_byte(1);
+                dest.write(signature);
+            }
+            Self::Ecdsa(signature) => {
+                dest.push_byte(2);
+                dest.write(signature);
+            }
+        }
+    }
+}
+
+impl Decode for MultiSignature {
+    fn decode<I: scale::Input>(input: &mut I) -> std::result::Result<Self, scale::Error> {
+        match input.read_byte()? {
+            0 => Ok(Self::Ed25519(Decode::decode(input)?)),
+            1 => Ok(Self::Sr25519(Decode::decode(input)?)),
+            2 => Ok(Self::Ecdsa(Decode::decode(input)?)),
+            _ => Err("Invalid MultiSignature variant".into()),
+        }
+    }
+}
+
+/// Signs the payloads of signed extrinsics, e.g. as returned by the generated
+/// `extrinsic::signer_payload`.
+pub trait Signer {
+    /// The account id of the signer, i.e. the public key for sr25519 and ed25519, and the
+    /// `blake2_256` hash of the compressed public key for ecdsa.
+    fn account_id(&self) -> [u8; 32];
+
+    /// Sign the message as is.
+    fn sign_message(&self, message: &[u8]) -> MultiSignature;
+
+    /// Sign the payload of an extrinsic, hashing it first if it is longer than
+    /// [`MAX_UNHASHED_PAYLOAD_LEN`] bytes.
+    fn sign(&self, payload: &[u8]) -> MultiSignature {
+        if payload.len() > MAX_UNHASHED_PAYLOAD_LEN {
+            self.sign_message(&sp_core_hashing::blake2_256(payload))
+        } else {
+            self.sign_message(payload)
+        }
+    }
+}
+
+/// An sr25519 key pair, as used by default for Substrate accounts.
+pub struct Sr25519Signer {
+    keypair: schnorrkel::Keypair,
+}
+
+impl Sr25519Signer {
+    /// The signing context of Substrate's sr25519 signatures.
+    const SIGNING_CONTEXT: &'static [u8] = b"substrate";
+
+    /// Create the key pair from its 32 byte mini secret key, i.e. the seed of a Substrate key.
+    pub fn from_seed(seed: &[u8; 32]) -> Result<Self> {
+        let secret = schnorrkel::MiniSecretKey::from_bytes(seed)
+            .map_err(|_| Error::InvalidSecretKey { scheme: "sr25519" })?;
+        let keypair = secret.expand_to_keypair(schnorrkel::ExpansionMode::Ed25519);
+        Ok(Self { keypair })
+    }
+}
+
+impl Signer for Sr25519Signer {
+    fn account_id(&self) -> [u8; 32] {
+        self.keypair.public.to_bytes()
+    }
+
+    fn sign_message(&self, message: &[u8]) -> MultiSignature {
+        let context = schnorrkel::signing_context(Self::SIGNING_CONTEXT);
+        MultiSignature::Sr25519(self.keypair.sign(context.bytes(message)).to_bytes())
+    }
+}
+
+/// An ed25519 key pair.
+pub struct Ed25519Signer {
+    keypair: ed25519_dalek::Keypair,
+}
+
+impl Ed25519Signer {
+    /// Create the key pair from its 32 byte secret key.
+    pub fn from_seed(seed: &[u8; 32]) -> Result<Self> {
+        let secret = ed25519_dalek::SecretKey::from_bytes(seed)
+            .map_err(|_| Error::InvalidSecretKey { scheme: "ed25519" })?;
+        let public = ed25519_dalek::PublicKey::from(&secret);
+        Ok(Self {
+            keypair: ed25519_dalek::Keypair { secret, public },
+        })
+    }
+}
+
+impl Signer for Ed25519Signer {
+    fn account_id(&self) -> [u8; 32] {
+        self.keypair.public.to_bytes()
+    }
+
+    fn sign_message(&self, message: &[u8]) -> MultiSignature {
+        use ed25519_dalek::Signer as _;
+        MultiSignature::Ed25519(self.keypair.sign(message).to_bytes())
+    }
+}
+
+/// A secp256k1 ecdsa key pair.
+pub struct EcdsaSigner {
+    secret: libsecp256k1::SecretKey,
+}
+
+impl EcdsaSigner {
+    /// Create the key pair from its 32 byte secret key.
+    pub fn from_seed(seed: &[u8; 32]) -> Result<Self> {
+        let secret = libsecp256k1::SecretKey::parse(seed)
+            .map_err(|_| Error::InvalidSecretKey { scheme: "ecdsa" })?;
+        Ok(Self { secret })
+    }
+
+    /// The public key in its 33 byte compressed form.
+    pub fn public_key(&self) -> [u8; 33] {
+        libsecp256k1::PublicKey::from_secret_key(&self.secret).serialize_compressed()
+    }
+}
+
+impl Signer for EcdsaSigner {
+    fn account_id(&self) -> [u8; 32] {
+        sp_core_hashing::blake2_256(&self.public_key())
+    }
+
+    /// Sign the `blake2_256` hash of the message, like Substrate's ecdsa key pairs.
+    fn sign_message(&self, message: &[u8]) -> MultiSignature {
+        let message = libsecp256k1::Message::parse(&sp_core_hashing::blake2_256(message));
+        let (signature, recovery_id) = libsecp256k1::sign(&message, &self.secret);
+        let mut bytes = [0; 65];
+        bytes[..64].copy_from_slice(&signature.serialize());
+        bytes[64] = recovery_id.serialize();
+        MultiSignature::Ecdsa(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto as _;
+
+    #[test]
+    fn ed25519_signature() {
+        // test 2 of RFC 8032
+        let seed = hex::decode("4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb")
+            .unwrap();
+        let signer = Ed25519Signer::from_seed(seed[..].try_into().unwrap()).unwrap();
+
+        assert_eq!(
+            hex::encode(signer.account_id()),
+            "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c"
+        );
+        assert_eq!(
+            signer.sign(&[0x72]),
+            MultiSignature::Ed25519(
+                hex::decode(
+                    "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da\
+                     085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00"
+                )
+                .unwrap()[..]
+                    .try_into()
+                    .unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn sr25519_signature() {
+        let signer = Sr25519Signer::from_seed(&[1; 32]).unwrap();
+        let public = schnorrkel::PublicKey::from_bytes(&signer.account_id()).unwrap();
+        let context = schnorrkel::signing_context(b"substrate");
+
+        let signature = match signer.sign(b"payload") {
+            MultiSignature::Sr25519(signature) => signature,
+            other => panic!("Expected an sr25519 signature, got {:?}", other),
+        };
+        let signature = schnorrkel::Signature::from_bytes(&signature).unwrap();
+
+        assert!(public.verify(context.bytes(b"payload"), &signature).is_ok());
+    }
+
+    #[test]
+    fn ecdsa_signature() {
+        let signer = EcdsaSigner::from_seed(&[1; 32]).unwrap();
+
+        let signature = match signer.sign(b"payload") {
+            MultiSignature::Ecdsa(signature) => signature,
+            other => panic!("Expected an ecdsa signature, got {:?}", other),
+        };
+        let message = libsecp256k1::Message::parse(&sp_core_hashing::blake2_256(b"payload"));
+        let recovery_id = libsecp256k1::RecoveryId::parse(signature[64]).unwrap();
+        let signature = libsecp256k1::Signature::parse_standard_slice(&signature[..64]).unwrap();
+        let public = libsecp256k1::recover(&message, &signature, &recovery_id).unwrap();
+
+        assert_eq!(public.serialize_compressed(), signer.public_key());
+        assert!(EcdsaSigner::from_seed(&[0; 32]).is_err());
+    }
+
+    #[test]
+    fn long_payloads_are_hashed() {
+        let signer = Ed25519Signer::from_seed(&[1; 32]).unwrap();
+        let payload = [7; MAX_UNHASHED_PAYLOAD_LEN + 1];
+
+        assert_eq!(
+            signer.sign(&payload),
+            signer.sign_message(&sp_core_hashing::blake2_256(&payload))
+        );
+        assert_eq!(
+            signer.sign(&payload[..MAX_UNHASHED_PAYLOAD_LEN]),
+            signer.sign_message(&payload[..MAX_UNHASHED_PAYLOAD_LEN])
+        );
+    }
+
+    #[test]
+    fn multi_signature_encoding() {
+        let sr25519 = MultiSignature::Sr25519([2; 64]).encode();
+        let ecdsa = MultiSignature::Ecdsa([3; 65]).encode();
+
+        assert_eq!(sr25519[0], 1);
+        assert_eq!(&sr25519[1..], &[2; 64][..]);
+        assert_eq!(ecdsa[0], 2);
+        assert_eq!(ecdsa.len(), 66);
+        assert_eq!(
+            MultiSignature::decode(&mut &ecdsa[..]).unwrap(),
+            MultiSignature::Ecdsa([3; 65])
+        );
+    }
+}
@@ -0,0 +1,9 @@
+//! Helpers shared by the unit tests.
+
+use std::{env, fs, path};
+
+/// Read a metadata file, relative to the crate root.
+pub fn read_metadata(file: &str) -> Vec<u8> {
+    let root = env::var("CARGO_MANIFEST_DIR").unwrap_or(".".into());
+    fs::read(path::Path::new(&root).join(file)).unwrap()
+}
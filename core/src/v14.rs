@@ -0,0 +1,445 @@
+//! Support for the final V14 metadata format.
+//!
+//! V14 metadata is converted into the V13 representation understood by the
+//! [`crate::generate_runtime::RuntimeGenerator`], so the same pallet modules are generated from
+//! both formats. The conversion SCALE encodes the V14 metadata with the V13 layout and decodes the
+//! result, mapping the differences between the two formats:
+//!
+//! - type ids are 0 based in V14 and 1 based in V13.
+//! - type parameters which are skipped in V14 (those without a type) are omitted.
+//! - the calls of a pallet are a variant type in V14, from which the list of V13 calls is built.
+//! - V14 storage maps have a list of hashers, which become V13 `Map` or `NMap` entries.
+//! - bit sequences become `bitvec::vec::BitVec<O, T>` composites, with the bit order and store
+//!   types as type parameters.
+//! - the types of the additional signed data of signed extensions are dropped.
+
+use crate::{Error, Result};
+use frame_metadata::v13::RuntimeMetadataV13;
+use frame_metadata_v14::{
+    PalletMetadata, RuntimeMetadataV14, StorageEntryModifier, StorageEntryType, StorageHasher,
+};
+use scale::Decode as _;
+use scale_info_v2::{
+    form::PortableForm, Field, PortableRegistry, TypeDef, TypeDefPrimitive, Variant,
+};
+use std::collections::HashMap;
+
+/// Convert V14 metadata into the V13 representation.
+pub fn into_v13(metadata: &RuntimeMetadataV14) -> Result<RuntimeMetadataV13> {
+    let mut encoder = V13Encoder::new(&metadata.types);
+    encoder.encode_metadata(metadata)?;
+    Ok(RuntimeMetadataV13::decode(&mut &encoder.bytes[..])?)
+}
+
+/// Encodes V14 metadata with the layout of V13 metadata.
+struct V13Encoder<'a> {
+    types: &'a PortableRegistry,
+    /// Maps the ids of V14 types onto the ids of the V13 types.
+    ids: HashMap<u32, u32>,
+    bytes: Vec<u8>,
+}
+
+impl<'a> V13Encoder<'a> {
+    fn new(types: &'a PortableRegistry) -> Self {
+        let ids = types
+            .types
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| (ty.id, i as u32 + 1))
+            .collect();
+        Self {
+            types,
+            ids,
+            bytes: Vec::new(),
+        }
+    }
+
+    fn encode_metadata(&mut self, metadata: &RuntimeMetadataV14) -> Result<()> {
+        self.compact_len(self.types.types.len());
+        for ty in &self.types.types {
+            self.encode_type(ty.id)?;
+        }
+
+        self.compact_len(metadata.pallets.len());
+        for pallet in &metadata.pallets {
+            self.encode_pallet(pallet)?;
+        }
+
+        let extrinsic = &metadata.extrinsic;
+        self.type_id(extrinsic.ty.id)?;
+        self.push(&extrinsic.version);
+        self.compact_len(extrinsic.signed_extensions.len());
+        for signed_extension in &extrinsic.signed_extensions {
+            self.push(&signed_extension.identifier);
+            self.type_id(signed_extension.ty.id)?;
+        }
+        Ok(())
+    }
+
+    fn encode_type(&mut self, id: u32) -> Result<()> {
+        let types = self.types;
+        let ty = &types
+            .types
+            .iter()
+            .find(|ty| ty.id == id)
+            .ok_or(Error::TypeNotFound { id })?
+            .ty;
+
+        if let TypeDef::BitSequence(ref bits) = ty.type_def {
+            // V13 has no bit sequences, which are represented by a `bitvec::vec::BitVec<O, T>`
+            // composite without fields instead
+            self.push(&["bitvec", "vec", "BitVec"][..]);
+            self.compact_len(2);
+            self.type_id(bits.bit_order_type.id)?;
+            self.type_id(bits.bit_store_type.id)?;
+            self.push(&0u8);
+            self.encode_fields(&[])?;
+            self.push(&ty.docs);
+            return Ok(());
+        }
+
+        self.push(&ty.path.segments);
+        let type_params = ty
+            .type_params
+            .iter()
+            .filter_map(|param| param.ty.as_ref())
+            .collect::<Vec<_>>();
+        self.compact_len(type_params.len());
+        for param in type_params {
+            self.type_id(param.id)?;
+        }
+
+        match ty.type_def {
+            TypeDef::Composite(ref composite) => {
+                self.push(&0u8);
+                self.encode_fields(&composite.fields)?;
+            }
+            TypeDef::Variant(ref variant) => {
+                self.push(&1u8);
+                self.compact_len(variant.variants.len());
+                for (i, v) in variant.variants.iter().enumerate() {
+                    self.encode_variant(i, v)?;
+                }
+            }
+            TypeDef::Sequence(ref sequence) => {
+                self.push(&2u8);
+                self.type_id(sequence.type_param.id)?;
+            }
+            TypeDef::Array(ref array) => {
+                self.push(&3u8);
+                self.push(&array.len);
+                self.type_id(array.type_param.id)?;
+            }
+            TypeDef::Tuple(ref tuple) => {
+                self.push(&4u8);
+                self.compact_len(tuple.fields.len());
+                for field in &tuple.fields {
+                    self.type_id(field.id)?;
+                }
+            }
+            TypeDef::Primitive(ref primitive) => {
+                self.push(&5u8);
+                self.push(&primitive_index(primitive));
+            }
+            TypeDef::Compact(ref compact) => {
+                self.push(&6u8);
+                self.type_id(compact.type_param.id)?;
+            }
+            TypeDef::BitSequence(_) => unreachable!("bit sequences are encoded above"),
+        }
+
+        self.push(&ty.docs);
+        Ok(())
+    }
+
+    fn encode_fields(&mut self, fields: &[Field<PortableForm>]) -> Result<()> {
+        self.compact_len(fields.len());
+        for field in fields {
+            self.push(&field.name);
+            self.type_id(field.ty.id)?;
+            self.push(&field.type_name.clone().unwrap_or_default());
+            self.push(&field.docs);
+        }
+        Ok(())
+    }
+
+    fn encode_variant(&mut self, position: usize, variant: &Variant<PortableForm>) -> Result<()> {
+        self.push(&variant.name);
+        self.encode_fields(&variant.fields)?;
+        // V13 has no variant indices, keep the index as the discriminant if it differs from the
+        // position of the variant
+        let discriminant = if variant.index as usize != position {
+            Some(variant.index as u64)
+        } else {
+            None
+        };
+        self.push(&discriminant);
+        self.push(&variant.docs);
+        Ok(())
+    }
+
+    fn encode_pallet(&mut self, pallet: &PalletMetadata<PortableForm>) -> Result<()> {
+        self.push(&pallet.name);
+
+        match pallet.storage {
+            Some(ref storage) => {
+                self.push(&1u8);
+                self.push(&storage.prefix);
+                self.compact_len(storage.entries.len());
+                for entry in &storage.entries {
+                    self.push(&entry.name);
+                    self.push(&match entry.modifier {
+                        StorageEntryModifier::Optional => 0u8,
+                        StorageEntryModifier::Default => 1u8,
+                    });
+                    match entry.ty {
+                        StorageEntryType::Plain(ref value) => {
+                            self.push(&0u8);
+                            self.type_id(value.id)?;
+                        }
+                        StorageEntryType::Map {
+                            ref hashers,
+                            ref key,
+                            ref value,
+                        } if hashers.len() == 1 => {
+                            self.push(&1u8);
+                            self.push(&hasher_index(&hashers[0]));
+                            self.type_id(key.id)?;
+                            self.type_id(value.id)?;
+                            // unused
+                            self.push(&false);
+                        }
+                        StorageEntryType::Map {
+                            ref hashers,
+                            ref key,
+                            ref value,
+                        } => {
+                            self.push(&3u8);
+                            self.type_id(key.id)?;
+                            self.push(&hashers.iter().map(hasher_index).collect::<Vec<_>>());
+                            self.type_id(value.id)?;
+                        }
+                    }
+                    self.push(&entry.default);
+                    self.push(&entry.docs);
+                }
+            }
+            None => self.push(&0u8),
+        }
+
+        match pallet.calls {
+            Some(ref calls) => {
+                self.push(&1u8);
+                self.type_id(calls.ty.id)?;
+                let mut variants = self.variants(calls.ty.id)?.iter().collect::<Vec<_>>();
+                variants.sort_by_key(|v| v.index);
+                self.compact_len(variants.len());
+                for call in variants {
+                    self.push(&call.name);
+                    self.compact_len(call.fields.len());
+                    for (i, arg) in call.fields.iter().enumerate() {
+                        let name = arg.name.clone().unwrap_or_else(|| format!("_{}", i));
+                        self.push(&name);
+                        self.type_id(arg.ty.id)?;
+                    }
+                    self.push(&call.docs);
+                }
+            }
+            None => self.push(&0u8),
+        }
+
+        let event = pallet.event.as_ref().map(|event| event.ty.id);
+        self.optional_type_id(event)?;
+
+        self.compact_len(pallet.constants.len());
+        for constant in &pallet.constants {
+            self.push(&constant.name);
+            self.type_id(constant.ty.id)?;
+            self.push(&constant.value);
+            self.push(&constant.docs);
+        }
+
+        let error = pallet.error.as_ref().map(|error| error.ty.id);
+        self.optional_type_id(error)?;
+
+        self.push(&pallet.index);
+        Ok(())
+    }
+
+    fn variants(&self, id: u32) -> Result<&'a [Variant<PortableForm>]> {
+        let types = self.types;
+        let ty = types
+            .types
+            .iter()
+            .find(|ty| ty.id == id)
+            .ok_or(Error::TypeNotFound { id })?;
+        match ty.ty.type_def {
+            TypeDef::Variant(ref variant) => Ok(&variant.variants),
+            _ => Err(Error::ExpectedVariantType { id }),
+        }
+    }
+
+    fn type_id(&mut self, id: u32) -> Result<()> {
+        let v13_id = *self.ids.get(&id).ok_or(Error::TypeNotFound { id })?;
+        self.push(&v13_id);
+        Ok(())
+    }
+
+    fn optional_type_id(&mut self, id: Option<u32>) -> Result<()> {
+        match id {
+            Some(id) => {
+                self.push(&1u8);
+                self.type_id(id)
+            }
+            None => {
+                self.push(&0u8);
+                Ok(())
+            }
+        }
+    }
+
+    fn compact_len(&mut self, len: usize) {
+        self.push(&scale::Compact(len as u32));
+    }
+
+    fn push<T: scale::Encode + ?Sized>(&mut self, value: &T) {
+        value.encode_to(&mut self.bytes);
+    }
+}
+
+fn primitive_index(primitive: &TypeDefPrimitive) -> u8 {
+    match primitive {
+        TypeDefPrimitive::Bool => 0,
+        TypeDefPrimitive::Char => 1,
+        TypeDefPrimitive::Str => 2,
+        TypeDefPrimitive::U8 => 3,
+        TypeDefPrimitive::U16 => 4,
+        TypeDefPrimitive::U32 => 5,
+        TypeDefPrimitive::U64 => 6,
+        TypeDefPrimitive::U128 => 7,
+        TypeDefPrimitive::U256 => 8,
+        TypeDefPrimitive::I8 => 9,
+        TypeDefPrimitive::I16 => 10,
+        TypeDefPrimitive::I32 => 11,
+        TypeDefPrimitive::I64 => 12,
+        TypeDefPrimitive::I128 => 13,
+        TypeDefPrimitive::I256 => 14,
+    }
+}
+
+fn hasher_index(hasher: &StorageHasher) -> u8 {
+    match hasher {
+        StorageHasher::Blake2_128 => 0,
+        StorageHasher::Blake2_256 => 1,
+        StorageHasher::Blake2_128Concat => 2,
+        StorageHasher::Twox128 => 3,
+        StorageHasher::Twox256 => 4,
+        StorageHasher::Twox64Concat => 5,
+        StorageHasher::Identity => 6,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::read_metadata;
+    use frame_metadata::v13;
+
+    fn test_runtime_v13() -> RuntimeMetadataV13 {
+        v13_metadata("test-runtime-v14.scale")
+    }
+
+    fn v13_metadata(file: &str) -> RuntimeMetadataV13 {
+        let bytes = read_metadata(file);
+        let metadata: frame_metadata_v14::RuntimeMetadataPrefixed =
+            scale_v3::Decode::decode(&mut &bytes[..]).unwrap();
+        match metadata.1 {
+            frame_metadata_v14::RuntimeMetadata::V14(metadata) => into_v13(&metadata).unwrap(),
+            _ => panic!("expected V14 metadata"),
+        }
+    }
+
+    #[test]
+    fn pallet_calls_from_variant_type() {
+        let metadata = test_runtime_v13();
+        let balances = &metadata.pallets[1];
+
+        assert_eq!(balances.name, "Balances");
+        assert_eq!(balances.index, 5);
+        let calls = &balances.calls.as_ref().unwrap().calls;
+        let names = calls
+            .iter()
+            .map(|call| call.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["transfer", "transfer_keep_alive"]);
+        let args = calls[0]
+            .arguments
+            .iter()
+            .map(|arg| arg.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(args, ["dest", "value"]);
+    }
+
+    #[test]
+    fn storage_maps_with_multiple_hashers_are_n_maps() {
+        let metadata = test_runtime_v13();
+        let system = &metadata.pallets[0];
+        let entries = &system.storage.as_ref().unwrap().entries;
+
+        assert!(matches!(entries[0].ty, v13::StorageEntryType::Map { .. }));
+        assert_eq!(entries[3].name, "EventTopics");
+        assert!(matches!(
+            entries[3].ty,
+            v13::StorageEntryType::NMap { ref hashers, .. } if hashers.len() == 2
+        ));
+    }
+
+    #[test]
+    fn call_indices_are_kept() {
+        let metadata = v13_metadata("test-runtime-v14-indices.scale");
+        let example = &metadata.pallets[2];
+        let calls = example.calls.as_ref().unwrap();
+
+        assert_eq!(example.name, "Example");
+        let ty = metadata.types.resolve(calls.ty.id()).unwrap();
+        let variants = match ty.type_def() {
+            scale_info::TypeDef::Variant(variant) => variant.variants(),
+            _ => panic!("expected a variant type"),
+        };
+        let indices = variants
+            .iter()
+            .map(|v| (v.name().as_str(), v.discriminant()))
+            .collect::<Vec<_>>();
+        assert_eq!(indices, [("store", Some(3)), ("clear", Some(7))]);
+    }
+
+    #[test]
+    fn bit_sequences_are_bitvec_composites() {
+        let metadata = v13_metadata("test-runtime-v14-indices.scale");
+        let example = &metadata.pallets[2];
+        let entry = &example.storage.as_ref().unwrap().entries[0];
+        let id = match entry.ty {
+            v13::StorageEntryType::Plain(ref ty) => ty.id(),
+            _ => panic!("expected a plain storage entry"),
+        };
+
+        let ty = metadata.types.resolve(id).unwrap();
+        assert_eq!(ty.path().segments(), ["bitvec", "vec", "BitVec"]);
+        assert!(
+            matches!(ty.type_def(), scale_info::TypeDef::Composite(c) if c.fields().is_empty())
+        );
+        let params = ty
+            .type_params()
+            .iter()
+            .map(|param| {
+                metadata
+                    .types
+                    .resolve(param.id())
+                    .unwrap()
+                    .path()
+                    .segments()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(params, [&["bitvec", "order", "Lsb0"][..], &[]]);
+    }
+}
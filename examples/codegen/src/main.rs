@@ -2,9 +2,9 @@
 //!
 //! Generated with:
 //! ```
-//! cargo run -p chameleon-cli \
-//!  | rustfmt --edition=2018 --emit=stdout \
-//!  > examples/codegen/src/substrate_node_runtime.rs
+//! cargo run -p chameleon-cli -- \
+//!  --metadata core/node-runtime.scale \
+//!  --output examples/codegen/src/substrate_node_runtime.rs
 //! ```
 mod substrate_node_runtime;
 
@@ -1,15 +1,208 @@
 use proc_macro::TokenStream;
+use syn::{
+    parenthesized,
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    punctuated::Punctuated,
+    Token,
+};
 
+/// Generate the runtime types from a metadata file, relative to the crate root.
+///
+/// `primitive_types` maps `U256` values onto `primitive_types::U256`, instead of generating a
+/// self-contained type. Derives can be added to all generated types with `derive(..)`, and to
+/// single types with `derive_for_type(<type path>, ..)`. Existing Rust types can be used instead of
+/// generated types with `substitute_type(<type path>, <rust path>)`. The type of the additional
+/// signed data of a signed extension unknown to the generator is given with
+/// `additional_signed(<identifier>, <rust type>)`. For legacy (V12) metadata, the Rust types of its
+/// type names are read from the file given with `legacy_types(..)`, relative to the crate root.
+/// `compatibility_check` embeds the structural hashes of the metadata, with a
+/// `check_compatibility` function to check the metadata of a node against them at runtime.
+/// `tolerant_events` generates `EventRecords::decode_tolerant`, which keeps the bytes of events
+/// unknown to the generated code instead of failing. `runtime_apis(..)` generates `state_call`
+/// functions for the runtime API methods declared in the given file, relative to the crate root:
+///
+/// ```ignore
+/// generate_types!(
+///     "node-runtime.scale",
+///     primitive_types,
+///     derive(Clone, PartialEq),
+///     derive_for_type(sp_core::crypto::AccountId32, Hash),
+///     substitute_type(sp_arithmetic::per_things::Perbill, ::sp_arithmetic::Perbill),
+///     additional_signed(CheckFoo, u32),
+///     legacy_types("legacy-types.txt"),
+///     compatibility_check,
+///     tolerant_events,
+///     runtime_apis("runtime-apis.txt"),
+/// );
+/// ```
 #[proc_macro]
 pub fn generate_types(input: TokenStream) -> TokenStream {
-    let input = input.to_string();
-    let input = input.trim_matches('"');
+    let input = parse_macro_input!(input as GenerateTypesInput);
 
     let root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".into());
     let root_path = std::path::Path::new(&root);
-    let path = root_path.join(input);
+    let path = root_path.join(input.path.value());
 
-    core::generate_runtime_types("runtime", path)
-        .expect("Runtime generation failed")
+    let mut config = chameleon_core::TypeGeneratorConfig {
+        primitive_types: input.primitive_types,
+        compatibility_check: input.compatibility_check,
+        tolerant_events: input.tolerant_events,
+        ..Default::default()
+    };
+    for derive in input.derives {
+        config = config.derive(derive);
+    }
+    for (type_path, derive) in input.type_derives {
+        config = config.derive_for_type(type_path, derive);
+    }
+    for (type_path, substitute) in input.type_substitutes {
+        config = config.substitute_type(type_path, substitute);
+    }
+    for (identifier, ty) in input.additional_signed_types {
+        config = config.additional_signed_type(identifier, ty);
+    }
+    if let Some(legacy_types) = input.legacy_types {
+        let legacy_types_path = root_path.join(legacy_types.value());
+        let registry = std::fs::read_to_string(&legacy_types_path)
+            .map_err(chameleon_core::Error::from)
+            .and_then(|source| chameleon_core::LegacyTypeRegistry::parse(&source));
+        match registry {
+            Ok(registry) => config.legacy_types = registry,
+            Err(err) => {
+                let msg = format!("Reading legacy types failed: {}", err);
+                return syn::Error::new(legacy_types.span(), msg)
+                    .to_compile_error()
+                    .into();
+            }
+        }
+    }
+    if let Some(runtime_apis) = input.runtime_apis {
+        let runtime_apis_path = root_path.join(runtime_apis.value());
+        let apis = std::fs::read_to_string(&runtime_apis_path)
+            .map_err(chameleon_core::Error::from)
+            .and_then(|source| chameleon_core::RuntimeApis::parse(&source));
+        match apis {
+            Ok(apis) => config.runtime_apis = apis,
+            Err(err) => {
+                let msg = format!("Reading runtime APIs failed: {}", err);
+                return syn::Error::new(runtime_apis.span(), msg)
+                    .to_compile_error()
+                    .into();
+            }
+        }
+    }
+
+    chameleon_core::generate_runtime_types("runtime", path, config)
+        .unwrap_or_else(|err| {
+            let msg = format!("Runtime generation failed: {}", err);
+            syn::Error::new(proc_macro2::Span::call_site(), msg).to_compile_error()
+        })
         .into()
 }
+
+struct GenerateTypesInput {
+    path: syn::LitStr,
+    derives: Vec<syn::Path>,
+    type_derives: Vec<(String, syn::Path)>,
+    type_substitutes: Vec<(String, syn::Path)>,
+    additional_signed_types: Vec<(String, syn::Type)>,
+    legacy_types: Option<syn::LitStr>,
+    runtime_apis: Option<syn::LitStr>,
+    primitive_types: bool,
+    compatibility_check: bool,
+    tolerant_events: bool,
+}
+
+impl Parse for GenerateTypesInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let path = input.parse()?;
+        let mut derives = Vec::new();
+        let mut type_derives = Vec::new();
+        let mut type_substitutes = Vec::new();
+        let mut additional_signed_types = Vec::new();
+        let mut legacy_types = None;
+        let mut runtime_apis = None;
+        let mut primitive_types = false;
+        let mut compatibility_check = false;
+        let mut tolerant_events = false;
+
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            let option: syn::Ident = input.parse()?;
+            if option == "primitive_types" {
+                primitive_types = true;
+                continue;
+            }
+            if option == "compatibility_check" {
+                compatibility_check = true;
+                continue;
+            }
+            if option == "tolerant_events" {
+                tolerant_events = true;
+                continue;
+            }
+            let content;
+            parenthesized!(content in input);
+            if option == "derive" {
+                let paths = Punctuated::<syn::Path, Token![,]>::parse_terminated(&content)?;
+                derives.extend(paths);
+            } else if option == "derive_for_type" {
+                let type_path = parse_type_path(&content)?;
+                content.parse::<Token![,]>()?;
+                let paths = Punctuated::<syn::Path, Token![,]>::parse_terminated(&content)?;
+                type_derives.extend(paths.into_iter().map(|path| (type_path.clone(), path)));
+            } else if option == "substitute_type" {
+                let type_path = parse_type_path(&content)?;
+                content.parse::<Token![,]>()?;
+                let substitute = content.parse()?;
+                content.parse::<Option<Token![,]>>()?;
+                type_substitutes.push((type_path, substitute));
+            } else if option == "additional_signed" {
+                let identifier: syn::Ident = content.parse()?;
+                content.parse::<Token![,]>()?;
+                let ty = content.parse()?;
+                content.parse::<Option<Token![,]>>()?;
+                additional_signed_types.push((identifier.to_string(), ty));
+            } else if option == "legacy_types" {
+                legacy_types = Some(content.parse()?);
+            } else if option == "runtime_apis" {
+                runtime_apis = Some(content.parse()?);
+            } else {
+                return Err(syn::Error::new(
+                    option.span(),
+                    "expected `primitive_types`, `derive`, `derive_for_type`, `substitute_type`, \
+                     `additional_signed`, `legacy_types`, `runtime_apis`, `compatibility_check` or \
+                     `tolerant_events`",
+                ));
+            }
+        }
+
+        Ok(Self {
+            path,
+            derives,
+            type_derives,
+            type_substitutes,
+            additional_signed_types,
+            legacy_types,
+            runtime_apis,
+            primitive_types,
+            compatibility_check,
+            tolerant_events,
+        })
+    }
+}
+
+/// Parse the path of a type in the metadata, e.g. `sp_core::crypto::AccountId32`.
+fn parse_type_path(input: ParseStream) -> syn::Result<String> {
+    let type_path: syn::Path = input.parse()?;
+    Ok(type_path
+        .segments
+        .iter()
+        .map(|segment| segment.ident.to_string())
+        .collect::<Vec<_>>()
+        .join("::"))
+}
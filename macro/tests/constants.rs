@@ -0,0 +1,12 @@
+chameleon_macro::generate_types!("../core/test-runtime-v14.scale");
+
+use runtime::{balances, system};
+
+#[test]
+fn decode_constants() {
+    assert_eq!(system::constants::block_hash_count().unwrap(), 2400);
+    assert_eq!(
+        balances::constants::existential_deposit().unwrap(),
+        1_000_000_000_000
+    );
+}
@@ -0,0 +1,109 @@
+chameleon_macro::generate_types!("../core/test-runtime-v14.scale", tolerant_events);
+
+use chameleon_core::events::{EventRecordsDecoder, RawEventRecord};
+use codec::Decode as _;
+use runtime::{
+    __runtime_types::frame_system::Phase, balances, system, EventRecords, TolerantEvent,
+    TolerantEventRecord,
+};
+
+/// The `System::Events` value of a block with a transfer, encoded for the test runtime.
+const EVENTS: &str = "\
+    0c0000000000000040aa9809000000000000000100000005011111111111111111111111111111111111111111111111\
+    11111111111111111122222222222222222222222222222222222222222222222222222222222222220010a5d4e80000\
+    0000000000000000000001050022222222222222222222222222222222222222222222222222222222222222220010a5\
+    d4e80000000000000000000000043333333333333333333333333333333333333333333333333333333333333333";
+
+fn event_records() -> EventRecords {
+    let bytes = hex::decode(EVENTS).unwrap();
+    EventRecords::decode(&mut &bytes[..]).unwrap()
+}
+
+#[test]
+fn decode_event_records() {
+    let records = event_records();
+
+    let phases = records
+        .iter()
+        .map(|(phase, _, topics)| (phase, topics.len()))
+        .collect::<Vec<_>>();
+    assert!(matches!(
+        phases[..],
+        [
+            (Phase::ApplyExtrinsic(0), 0),
+            (Phase::ApplyExtrinsic(1), 0),
+            (Phase::Finalization, 1)
+        ]
+    ));
+    assert!(matches!(
+        records.0[0].event,
+        runtime::Event::System(system::Event::ExtrinsicSuccess { .. })
+    ));
+}
+
+#[test]
+fn find_pallet_events() {
+    let records = event_records();
+
+    let balances = records.find::<balances::Event>().collect::<Vec<_>>();
+    assert_eq!(balances.len(), 2);
+    assert!(matches!(
+        balances[0],
+        (
+            Phase::ApplyExtrinsic(1),
+            balances::Event::Transfer {
+                amount: 1_000_000_000_000,
+                ..
+            }
+        )
+    ));
+    match records.find_first::<balances::Event>() {
+        Some(balances::Event::Transfer { from, to, .. }) => {
+            assert_eq!(from.0, [0x11; 32]);
+            assert_eq!(to.0, [0x22; 32]);
+        }
+        other => panic!("Expected a transfer, got {:?}", other),
+    }
+    assert_eq!(records.find::<system::Event>().count(), 1);
+}
+
+#[test]
+fn decode_event_records_tolerantly() {
+    let metadata = std::fs::read("../core/test-runtime-v14.scale").unwrap();
+    let decoder = EventRecordsDecoder::new(&metadata).unwrap();
+    let bytes = hex::decode(EVENTS).unwrap();
+
+    let records = EventRecords::decode_tolerant(&decoder, &bytes).unwrap();
+    assert_eq!(records.len(), 3);
+    assert!(matches!(records[1].phase, Phase::ApplyExtrinsic(1)));
+    assert!(matches!(
+        records[1].event,
+        TolerantEvent::Known(runtime::Event::Balances(balances::Event::Transfer { .. }))
+    ));
+    assert_eq!(records[2].topics.len(), 1);
+}
+
+#[test]
+fn unknown_events_keep_their_bytes() {
+    let record = RawEventRecord {
+        phase: vec![0, 2, 0, 0, 0],
+        pallet_index: 42,
+        variant_index: 3,
+        event: vec![42, 3, 1, 2, 3],
+        topics: vec![0],
+    };
+
+    let record = TolerantEventRecord::from_raw(record).unwrap();
+    assert!(matches!(record.phase, Phase::ApplyExtrinsic(2)));
+    match record.event {
+        TolerantEvent::Unknown {
+            pallet_index,
+            variant_index,
+            bytes,
+        } => {
+            assert_eq!((pallet_index, variant_index), (42, 3));
+            assert_eq!(bytes, [42, 3, 1, 2, 3]);
+        }
+        other => panic!("Expected an unknown event, got {:?}", other),
+    }
+}
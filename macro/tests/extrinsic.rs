@@ -0,0 +1,99 @@
+chameleon_macro::generate_types!("../core/test-runtime-v14.scale");
+
+use codec::{Decode as _, Encode as _};
+use runtime::{
+    __runtime_types::{
+        frame_system::extensions::{
+            check_genesis::CheckGenesis, check_mortality::CheckMortality, check_nonce::CheckNonce,
+            check_spec_version::CheckSpecVersion,
+        },
+        sp_core::{crypto::AccountId32, H256},
+        sp_runtime::{generic::era::Era, multiaddress::MultiAddress},
+    },
+    balances, extrinsic,
+};
+
+/// The signer payload of the transfer below, as encoded by `sp_runtime::generic::SignedPayload`.
+const PAYLOAD: &str = "\
+    0500000202020202020202020202020202020202020202020202020202020202020202e5c0001464000000aaaaaa\
+    aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\
+    aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+
+/// The signed transfer below, as encoded by `sp_runtime::generic::UncheckedExtrinsic`.
+const EXTRINSIC: &str = "\
+    29028400010101010101010101010101010101010101010101010101010101010101010101333333333333333333\
+    33333333333333333333333333333333333333333333333333333333333333333333333333333333333333333333\
+    33333333333333333300140500000202020202020202020202020202020202020202020202020202020202020202\
+    e5c0";
+
+/// An sr25519 `MultiSignature`.
+#[derive(Debug, PartialEq, codec::Encode, codec::Decode)]
+enum MultiSignature {
+    #[codec(index = 1)]
+    Sr25519([u8; 64]),
+}
+
+type UncheckedExtrinsic =
+    extrinsic::UncheckedExtrinsic<MultiAddress<AccountId32, ()>, MultiSignature>;
+
+fn transfer() -> runtime::Call {
+    runtime::Call::Balances(balances::calls::transfer(
+        MultiAddress::Id(AccountId32([2; 32])),
+        12345,
+    ))
+}
+
+fn extra() -> extrinsic::SignedExtra {
+    extrinsic::SignedExtra {
+        check_spec_version: CheckSpecVersion {},
+        check_genesis: CheckGenesis {},
+        check_mortality: CheckMortality(Era::Immortal),
+        check_nonce: CheckNonce(5),
+    }
+}
+
+#[test]
+fn signer_payload() {
+    let additional_signed = extrinsic::AdditionalSigned {
+        check_spec_version: 100,
+        check_genesis: H256([0xaa; 32]),
+        check_mortality: H256([0xaa; 32]),
+    };
+
+    let payload = extrinsic::signer_payload(&transfer(), &extra(), &additional_signed);
+
+    assert_eq!(hex::encode(payload), PAYLOAD);
+}
+
+#[test]
+fn signed_extrinsic() {
+    let xt = UncheckedExtrinsic::new_signed(
+        transfer(),
+        MultiAddress::Id(AccountId32([1; 32])),
+        MultiSignature::Sr25519([0x33; 64]),
+        extra(),
+    );
+
+    let bytes = xt.encode();
+    assert_eq!(hex::encode(&bytes), EXTRINSIC);
+
+    let xt = UncheckedExtrinsic::decode(&mut &bytes[..]).unwrap();
+    let (address, signature, extra) = xt.signature.as_ref().unwrap();
+    assert!(matches!(address, MultiAddress::Id(AccountId32(id)) if *id == [1; 32]));
+    assert_eq!(*signature, MultiSignature::Sr25519([0x33; 64]));
+    assert_eq!(extra.check_nonce.0, 5);
+    assert_eq!(xt.encode(), bytes);
+}
+
+#[test]
+fn unsigned_extrinsic() {
+    let xt = UncheckedExtrinsic::new_unsigned(transfer());
+
+    let bytes = xt.encode();
+    // the length, the version without the signed bit, and the call
+    assert_eq!(bytes[..2], [0x98, 0x04]);
+    assert!(UncheckedExtrinsic::decode(&mut &bytes[..])
+        .unwrap()
+        .signature
+        .is_none());
+}
@@ -0,0 +1,52 @@
+chameleon_macro::generate_types!("../core/test-runtime-v14-indices.scale");
+
+use bitvec::{bitvec, order::Lsb0};
+use codec::{Decode as _, Encode as _};
+use runtime::example;
+
+#[test]
+fn call_indices() {
+    let call = runtime::Call::Example(example::calls::clear());
+    assert_eq!(call.encode(), [7, 7]);
+
+    let call = runtime::Call::decode(&mut &[7, 7][..]).unwrap();
+    assert!(matches!(
+        call,
+        runtime::Call::Example(example::Call::Clear(_))
+    ));
+}
+
+#[test]
+fn bit_sequences() {
+    let bits = bitvec![Lsb0, u8; 1, 0, 1, 1, 0, 0, 0, 0, 1];
+    let call = runtime::Call::Example(example::calls::store(bits.clone()));
+    assert_eq!(call.encode(), [7, 3, 36, 0x0d, 0x01]);
+
+    let call = runtime::Call::decode(&mut &[7, 3, 36, 0x0d, 0x01][..]).unwrap();
+    assert!(matches!(
+        call,
+        runtime::Call::Example(example::Call::Store(store)) if store.bits == bits
+    ));
+}
+
+#[test]
+fn error_indices_and_fields() {
+    let error = runtime::RuntimeError::from_module_error(7, &[4, 10, 0, 0, 0]).unwrap();
+    assert!(matches!(
+        error,
+        runtime::RuntimeError::Example(example::Error::TooLong { max: 10 })
+    ));
+    let details = error.details();
+    assert_eq!((details.pallet, details.error), ("Example", "TooLong"));
+    assert_eq!(details.docs, "The bits are longer than the maximum.");
+
+    let error = runtime::RuntimeError::from_module_error(7, &[1]).unwrap();
+    assert_eq!(error.details().error, "Overflow");
+    assert_eq!(
+        runtime::RuntimeError::Example(example::Error::Invalid(3)).encode(),
+        [7, 6, 3, 0, 0, 0]
+    );
+
+    assert!(runtime::RuntimeError::from_module_error(7, &[0]).is_err());
+    assert!(runtime::RuntimeError::from_module_error(8, &[0]).is_err());
+}
@@ -0,0 +1,37 @@
+chameleon_macro::generate_types!(
+    "../core/test-runtime-v14.scale",
+    runtime_apis("tests/runtime-apis.txt"),
+);
+
+use codec::Encode as _;
+use runtime::{
+    __runtime_types::{frame_support::weights::Pays, sp_core::crypto::AccountId32},
+    runtime_api::{account_nonce_api, metadata, transaction_payment_api},
+};
+
+#[test]
+fn encode_runtime_api_calls() {
+    let call = account_nonce_api::account_nonce(AccountId32([1; 32]));
+    assert_eq!(call.method, "AccountNonceApi_account_nonce");
+    assert_eq!(call.args, [1; 32]);
+
+    let call = transaction_payment_api::query_info(vec![4, 5, 6], 3);
+    assert_eq!(call.method, "TransactionPaymentApi_query_info");
+    assert_eq!(call.args, [12, 4, 5, 6, 3, 0, 0, 0]);
+
+    let call = metadata::metadata();
+    assert_eq!(call.method, "Metadata_metadata");
+    assert!(call.args.is_empty());
+}
+
+#[test]
+fn decode_runtime_api_responses() {
+    let call = account_nonce_api::account_nonce(AccountId32([1; 32]));
+    assert_eq!(call.decode_response(&7u32.encode()).unwrap(), 7);
+    assert!(call.decode_response(&7u64.encode()).is_err());
+
+    let call = transaction_payment_api::query_info(vec![], 0);
+    let info = call.decode_response(&(1_000u64, 1u8).encode()).unwrap();
+    assert_eq!(info.weight, 1_000);
+    assert!(matches!(info.pays_fee, Pays::No));
+}
@@ -0,0 +1,79 @@
+chameleon_macro::generate_types!("../core/test-runtime-v14.scale");
+
+use codec::Encode as _;
+use runtime::{
+    __runtime_types::sp_core::{crypto::AccountId32, H256},
+    balances, system, StorageEntry,
+};
+
+/// The public key of the `//Alice` development account.
+const ALICE: &str = "d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d";
+
+fn alice() -> AccountId32 {
+    let mut account = [0; 32];
+    account.copy_from_slice(&hex::decode(ALICE).unwrap());
+    AccountId32(account)
+}
+
+#[test]
+fn plain_storage_key() {
+    assert_eq!(
+        hex::encode(balances::storage::TotalIssuance.key()),
+        "c2261276cc9d1f8598ea4b6a74b15c2f57c875e4cff74148e4628f264b974c80"
+    );
+}
+
+#[test]
+fn blake2_128_concat_storage_key() {
+    assert_eq!(
+        hex::encode(system::storage::Account(alice()).key()),
+        "26aa394eea5630e07c48ae0c9558cef7b99d880ec681799c0cf30e8886371da9\
+         de1e86a9a8c739864cf3cc5ec2bea59fd43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d"
+    );
+}
+
+#[test]
+fn twox_64_concat_storage_key() {
+    assert_eq!(
+        hex::encode(system::storage::BlockHash(0).key()),
+        "26aa394eea5630e07c48ae0c9558cef7a44704b568d21667356a5a050c118746b4def25cfda6ef3a00000000"
+    );
+}
+
+#[test]
+fn decode_storage_values() {
+    let issuance = 1_000_000_000_000u128.encode();
+    assert_eq!(
+        balances::storage::TotalIssuance::decode_value(Some(&issuance)).unwrap(),
+        Some(1_000_000_000_000)
+    );
+
+    let account = [
+        &[1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0][..],
+        &[
+            0x00, 0x10, 0xa5, 0xd4, 0xe8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ],
+        &[0; 48],
+    ]
+    .concat();
+    let account = system::storage::Account::decode_value(Some(&account))
+        .unwrap()
+        .unwrap();
+    assert_eq!((account.nonce, account.providers), (1, 1));
+    assert_eq!(account.data.free, 1_000_000_000_000);
+
+    assert!(system::storage::Account::decode_value(Some(&[1, 0])).is_err());
+}
+
+#[test]
+fn decode_default_storage_values() {
+    assert_eq!(
+        balances::storage::TotalIssuance::decode_value(None).unwrap(),
+        Some(0)
+    );
+
+    let hash: H256 = system::storage::BlockHash::decode_value(None)
+        .unwrap()
+        .unwrap();
+    assert_eq!(hash.0, [0; 32]);
+}
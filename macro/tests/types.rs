@@ -0,0 +1,80 @@
+chameleon_macro::generate_types!("../core/test-runtime-v14.scale", derive(Clone, PartialEq));
+
+use codec::{Decode as _, Encode as _};
+use runtime::{
+    __runtime_types::{
+        frame_system::{
+            extensions::{
+                check_genesis::CheckGenesis, check_mortality::CheckMortality,
+                check_nonce::CheckNonce, check_spec_version::CheckSpecVersion,
+            },
+            Phase,
+        },
+        sp_core::crypto::AccountId32,
+        sp_runtime::{generic::era::Era, multiaddress::MultiAddress},
+    },
+    balances, extrinsic, EventRecord, EventRecords,
+};
+
+#[test]
+fn compact_struct_fields() {
+    let transfer = balances::calls::Transfer {
+        dest: MultiAddress::Id(AccountId32([1; 32])),
+        value: 1_000_000_000_000,
+    };
+    let bytes = hex::decode(
+        "000101010101010101010101010101010101010101010101010101010101010101070010a5d4e8",
+    )
+    .unwrap();
+    assert_eq!(transfer.encode(), bytes);
+
+    let transfer = balances::calls::Transfer::decode(&mut &bytes[..]).unwrap();
+    assert!(matches!(transfer.dest, MultiAddress::Id(AccountId32(id)) if id == [1; 32]));
+    assert_eq!(transfer.value, 1_000_000_000_000);
+}
+
+#[test]
+fn compact_tuple_struct_fields() {
+    assert_eq!(CheckNonce(5).encode(), [0x14]);
+    assert_eq!(CheckNonce(1_000_000).encode(), [0x02, 0x09, 0x3d, 0x00]);
+
+    let nonce = CheckNonce::decode(&mut &[0x02, 0x09, 0x3d, 0x00][..]).unwrap();
+    assert_eq!(nonce.0, 1_000_000);
+}
+
+#[test]
+fn compact_enum_fields() {
+    let address = MultiAddress::<AccountId32, u32>::Index(1_000_000);
+    assert_eq!(address.encode(), [0x01, 0x02, 0x09, 0x3d, 0x00]);
+
+    let address = MultiAddress::<AccountId32, u32>::decode(&mut &[0x01, 0x14][..]).unwrap();
+    assert!(matches!(address, MultiAddress::Index(5)));
+}
+
+#[test]
+fn configured_derives() {
+    let call = runtime::Call::Balances(balances::calls::transfer(
+        MultiAddress::Id(AccountId32([1; 32])),
+        1_000,
+    ));
+    assert!(call.clone() == call);
+
+    let extra = extrinsic::SignedExtra {
+        check_spec_version: CheckSpecVersion {},
+        check_genesis: CheckGenesis {},
+        check_mortality: CheckMortality(Era::Immortal),
+        check_nonce: CheckNonce(0),
+    };
+    assert!(extra.clone() == extra);
+
+    let records = EventRecords(vec![EventRecord {
+        phase: Phase::Finalization,
+        event: runtime::Event::Balances(balances::Event::Transfer {
+            from: AccountId32([1; 32]),
+            to: AccountId32([2; 32]),
+            amount: 1_000,
+        }),
+        topics: vec![],
+    }]);
+    assert!(records.clone() == records);
+}